@@ -0,0 +1,26 @@
+-- Branches and transfers between them.
+-- Run this in the SQL editor of the database. If the tables use a salt (see [DBSettings]
+-- salt in config.ini), put it in front of every table name, e.g. "random_Branch".
+
+CREATE TABLE IF NOT EXISTS "Branch" (
+    id integer PRIMARY KEY,
+    name text NOT NULL,
+    address text NOT NULL DEFAULT ''
+);
+
+CREATE TABLE IF NOT EXISTS "Transfer" (
+    id integer PRIMARY KEY,
+    media_id integer NOT NULL,
+    source_branch integer NOT NULL,
+    destination_branch integer NOT NULL,
+    status text NOT NULL DEFAULT 'Requested',
+    requested_by integer NOT NULL,
+    requested_at bigint NOT NULL,
+    updated_at bigint NOT NULL
+);
+
+-- Every employee and media row is written with these columns, updates fail without them.
+-- 0 means the row is not assigned to a branch.
+ALTER TABLE "Employee" ADD COLUMN IF NOT EXISTS branch_id integer NOT NULL DEFAULT 0;
+ALTER TABLE "Media" ADD COLUMN IF NOT EXISTS branch_id integer NOT NULL DEFAULT 0;
+ALTER TABLE "Media" ADD COLUMN IF NOT EXISTS in_transit boolean NOT NULL DEFAULT false;
@@ -0,0 +1,25 @@
+-- Partner libraries and the titles borrowed from them.
+-- Run this in the SQL editor of the database. If the tables use a salt (see [DBSettings]
+-- salt in config.ini), put it in front of every table name, e.g. "random_PartnerLibrary".
+
+CREATE TABLE IF NOT EXISTS "PartnerLibrary" (
+    id integer PRIMARY KEY,
+    name text NOT NULL,
+    contact_email text NOT NULL DEFAULT '',
+    address text NOT NULL DEFAULT ''
+);
+
+CREATE TABLE IF NOT EXISTS "InterlibraryLoan" (
+    id integer PRIMARY KEY,
+    partner_id integer NOT NULL,
+    title text NOT NULL,
+    media_type text NOT NULL,
+    branch_id integer NOT NULL DEFAULT 0,
+    requested_by integer NOT NULL,
+    patron text,
+    status text NOT NULL DEFAULT 'Requested',
+    requested_at bigint NOT NULL,
+    updated_at bigint NOT NULL,
+    partner_due bigint,
+    patron_due bigint
+);
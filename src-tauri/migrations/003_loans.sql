@@ -0,0 +1,8 @@
+-- Due dates and renters of checked out media.
+-- Run this in the SQL editor of the database. If the tables use a salt (see [DBSettings]
+-- salt in config.ini), put it in front of every table name, e.g. "random_Media".
+
+-- Every media row is written with these columns, updates fail without them.
+-- Loans are counted against max_loans by renter_id, older loans only have the renter name.
+ALTER TABLE "Media" ADD COLUMN IF NOT EXISTS due bigint;
+ALTER TABLE "Media" ADD COLUMN IF NOT EXISTS renter_id integer;
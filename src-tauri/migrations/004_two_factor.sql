@@ -0,0 +1,11 @@
+-- Two-factor secrets, recovery codes and the last accepted time step, so a code cannot be
+-- used twice.
+-- Run this in the SQL editor of the database. If the tables use a salt (see [DBSettings]
+-- salt in config.ini), put it in front of every table name, e.g. "random_Employee".
+
+-- Every employee row is written with these columns, updates fail without them.
+ALTER TABLE "Employee" ADD COLUMN IF NOT EXISTS totp_secret text;
+ALTER TABLE "Employee" ADD COLUMN IF NOT EXISTS recovery_codes jsonb NOT NULL DEFAULT '[]';
+
+-- Written on every two-factor login, which fails without it.
+ALTER TABLE "Employee" ADD COLUMN IF NOT EXISTS totp_last_step bigint;
@@ -0,0 +1,24 @@
+-- Purchase requests and the vendor of each media copy.
+-- Run this in the SQL editor of the database. If the tables use a salt (see [DBSettings]
+-- salt in config.ini), put it in front of every table name, e.g. "random_PurchaseRequest".
+
+CREATE TABLE IF NOT EXISTS "PurchaseRequest" (
+    id integer PRIMARY KEY,
+    requested_by integer NOT NULL,
+    approver_id integer NOT NULL,
+    department text NOT NULL DEFAULT '',
+    project text NOT NULL DEFAULT '',
+    project_id integer,
+    title text NOT NULL,
+    media_type text NOT NULL,
+    vendor text NOT NULL DEFAULT '',
+    vendor_id integer,
+    price integer NOT NULL,
+    status text NOT NULL DEFAULT 'Pending',
+    requested_at bigint NOT NULL,
+    decided_at bigint,
+    media_id integer
+);
+
+-- Every media row is written with this column, so receiving a purchase fails without it.
+ALTER TABLE "Media" ADD COLUMN IF NOT EXISTS vendor_id integer;
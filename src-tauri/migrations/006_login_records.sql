@@ -0,0 +1,11 @@
+-- Failed login counters behind the lockout.
+-- Run this in the SQL editor of the database. If the tables use a salt (see [DBSettings]
+-- salt in config.ini), put it in front of every table name, e.g. "random_LoginRecord".
+
+-- Read on every login, which fails without it. The id is the employee's id.
+CREATE TABLE IF NOT EXISTS "LoginRecord" (
+    id integer PRIMARY KEY,
+    failed_attempts integer NOT NULL DEFAULT 0,
+    last_failure bigint NOT NULL DEFAULT 0,
+    locked_until bigint NOT NULL DEFAULT 0
+);
@@ -0,0 +1,15 @@
+-- The audit log of data changes, checkouts and returns.
+-- Run this in the SQL editor of the database. If the tables use a salt (see [DBSettings]
+-- salt in config.ini), put it in front of every table name, e.g. "random_AuditLog".
+
+-- Written after every audited change, which reports an error without it. Entries are sent
+-- without an id, so the database has to assign one.
+CREATE TABLE IF NOT EXISTS "AuditLog" (
+    id bigint GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    employee_id integer,
+    timestamp bigint NOT NULL,
+    action text NOT NULL,
+    table_name text NOT NULL,
+    record_id integer NOT NULL,
+    changes jsonb NOT NULL DEFAULT '{}'
+);
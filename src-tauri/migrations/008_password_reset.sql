@@ -0,0 +1,8 @@
+-- Password history and one-time reset codes.
+-- Run this in the SQL editor of the database. If the tables use a salt (see [DBSettings]
+-- salt in config.ini), put it in front of every table name, e.g. "random_Employee".
+
+-- Every employee row is written with these columns, updates fail without them.
+ALTER TABLE "Employee" ADD COLUMN IF NOT EXISTS password_history jsonb NOT NULL DEFAULT '[]';
+ALTER TABLE "Employee" ADD COLUMN IF NOT EXISTS reset_code text;
+ALTER TABLE "Employee" ADD COLUMN IF NOT EXISTS must_change_password boolean NOT NULL DEFAULT false;
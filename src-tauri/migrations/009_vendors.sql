@@ -0,0 +1,18 @@
+-- Vendors that media and purchases are bought from.
+-- Run this in the SQL editor of the database. If the tables use a salt (see [DBSettings]
+-- salt in config.ini), put it in front of every table name, e.g. "random_Vendor".
+
+CREATE TABLE IF NOT EXISTS "Vendor" (
+    id integer PRIMARY KEY,
+    name text NOT NULL,
+    contact_name text NOT NULL DEFAULT '',
+    email text NOT NULL DEFAULT '',
+    phone text NOT NULL DEFAULT '',
+    account_number text NOT NULL DEFAULT '',
+    notes text NOT NULL DEFAULT ''
+);
+
+-- Media and purchase request rows are written with these columns, updates fail without them.
+-- Both are also added by 005_purchase_requests.sql.
+ALTER TABLE "Media" ADD COLUMN IF NOT EXISTS vendor_id integer;
+ALTER TABLE "PurchaseRequest" ADD COLUMN IF NOT EXISTS vendor_id integer;
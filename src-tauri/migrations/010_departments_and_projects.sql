@@ -0,0 +1,23 @@
+-- Departments and projects that employees and purchases are charged to.
+-- Run this in the SQL editor of the database. If the tables use a salt (see [DBSettings]
+-- salt in config.ini), put it in front of every table name, e.g. "random_Department".
+
+CREATE TABLE IF NOT EXISTS "Department" (
+    id integer PRIMARY KEY,
+    name text NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS "Project" (
+    id integer PRIMARY KEY,
+    name text NOT NULL,
+    department_id integer,
+    start bigint NOT NULL,
+    "end" bigint,
+    budget bigint NOT NULL DEFAULT 0
+);
+
+-- Employee and purchase request rows are written with these columns, updates fail without
+-- them. project_id on PurchaseRequest is also added by 005_purchase_requests.sql.
+ALTER TABLE "Employee" ADD COLUMN IF NOT EXISTS department_id integer;
+ALTER TABLE "Employee" ADD COLUMN IF NOT EXISTS project_id integer;
+ALTER TABLE "PurchaseRequest" ADD COLUMN IF NOT EXISTS project_id integer;
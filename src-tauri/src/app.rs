@@ -1,162 +1,1470 @@
-use super::utils;
-use crate::app::data_manager::manager::{DbTool, DbToolError};
-use crate::types::enums::PermissionLevel;
-use crate::types::structs::{DisplayInfo, Employee, Media, Trie};
-use serde_json::Value;
+use crate::config::{Config, ProfileList, UiConfig};
+use crate::data_manager::manager::{DbTool, DbToolError};
+use crate::types::enums::{
+    AppEvent, AuditAction, InterlibraryLoanStatus, MediaType, PermissionLevel, Table, TotpStatus,
+    TransferStatus,
+};
+use crate::types::structs::{
+    AuditEntry, AuditFilter, Branch, BudgetSummary, Department, DisplayInfo, Employee,
+    EmployeeInfo, FieldKind, FieldSchema, InterlibraryLoan, LoginRecord, Media, OrgChart,
+    PartnerLibrary, Project, ProjectSpend, PurchaseRequest, RegistryMigration, SearchResult,
+    SpendReport, TableSchema, TotpEnrolment, Transfer, Trie, Vendor, VendorMerge, VendorReport,
+};
+use crate::utils;
+use serde_json::{Map, Value};
 use std::collections::HashMap;
-use std::mem;
-use std::sync::{Arc, Mutex};
-use tokio::runtime::Runtime;
-
-mod data_manager {
-    include!("data_manager.rs");
-}
+use std::future::Future;
+use tokio::sync::{broadcast, Mutex, RwLock, RwLockReadGuard};
 
+/// How many events a subscriber can fall behind before it starts missing them.
+const EVENT_CAPACITY: usize = 256;
 
+/// The library service behind every front end. All state sits behind async locks, so one
+/// `App` can be shared by concurrent commands without an outer lock: catalogue reads run in
+/// parallel, and nothing blocks on the runtime.
 pub struct App {
-    db_manager: DbTool,
-    employees: Arc<Mutex<HashMap<u16, Employee>>>,
+    db_manager: RwLock<DbTool>,
+    session: Mutex<Session>,
+    totp_key: Option<[u8; 32]>,
+    employees: RwLock<HashMap<u16, Employee>>,
+    media: RwLock<HashMap<u16, Media>>,
+    branches: RwLock<HashMap<u16, Branch>>,
+    interlibrary_loans: RwLock<HashMap<u16, InterlibraryLoan>>,
+    trie: RwLock<HashMap<&'static str, Trie>>,
+    active_profile: RwLock<String>,
+    events: broadcast::Sender<AppEvent>,
+    config: Config,
+}
+
+/// The signed-in user. An `App` has one session at a time.
+#[derive(Default)]
+struct Session {
     user: u16,
-    media: Arc<Mutex<HashMap<u16, Media>>>,
-    trie: HashMap<&'static str, Trie>,
-    rt: Runtime,
+    /// Failed logins from this app regardless of the account
+    attempts: LoginRecord,
+    totp_pending: bool,
+    totp_enrolment: Option<Vec<u8>>,
+}
+
+/// Runs `$body` with `$T` naming the record type of `$table`.
+macro_rules! with_record {
+    ($table:expr, $T:ident => $body:expr) => {
+        match $table {
+            Table::Employee => {
+                type $T = Employee;
+                $body
+            }
+            Table::Media => {
+                type $T = Media;
+                $body
+            }
+            Table::Branch => {
+                type $T = Branch;
+                $body
+            }
+            Table::PartnerLibrary => {
+                type $T = PartnerLibrary;
+                $body
+            }
+            Table::Vendor => {
+                type $T = Vendor;
+                $body
+            }
+            Table::Department => {
+                type $T = Department;
+                $body
+            }
+            Table::Project => {
+                type $T = Project;
+                $body
+            }
+        }
+    };
 }
 
 impl App {
-    pub fn new() -> App {
-        println!("Starting the library management system...");
-        let settings =
-            utils::loading::load_db_settings().expect("Failed to load database settings");
-        let rt = Runtime::new().unwrap();
-        let db_manager = rt
-            .block_on(DbTool::new(&settings))
-            .expect("Failed to connect to the database");
-        println!("Connected to the database");
-        let employees = Arc::new(Mutex::new(HashMap::<u16, Employee>::new()));
-        let media = Arc::new(Mutex::new(HashMap::<u16, Media>::new()));
-        let trie: HashMap<&'static str, Trie> = HashMap::new();
-        App {
-            db_manager,
-            employees,
-            user: 0,
-            media,
-            trie,
-            rt,
-        }
-    }
-
-    pub fn run(&mut self) {
-        self.refresh_all_data();
-    }
-
-    pub fn authenticate_employee(
-        &mut self,
+    pub async fn new(config: Config) -> Result<App, String> {
+        eprintln!("Starting the library management system...");
+        let db_manager = DbTool::new(&config.database)
+            .await
+            .map_err(|e| format!("Failed to connect to the database: {}", e))?;
+        eprintln!("Connected to the database");
+
+        let totp_passphrase = utils::loading::reveal_setting(&config.security.totp_key)
+            .map_err(|e| format!("Failed to decrypt totp_key: {}", e))?;
+        let totp_key = if totp_passphrase.is_empty() {
+            None
+        } else {
+            Some(utils::crypto::derive_key(&totp_passphrase).map_err(|e| e.to_string())?)
+        };
+        utils::security::configure(config.security.hashing.clone());
+
+        Ok(App {
+            db_manager: RwLock::new(db_manager),
+            session: Mutex::new(Session::default()),
+            totp_key,
+            employees: RwLock::default(),
+            media: RwLock::default(),
+            branches: RwLock::default(),
+            interlibrary_loans: RwLock::default(),
+            trie: RwLock::default(),
+            active_profile: RwLock::new(config.active_profile.clone()),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            config,
+        })
+    }
+
+    pub async fn run(&self) -> Result<(), String> {
+        self.refresh_all_data().await
+    }
+
+    /// Receives the changes made through this `App` from now on. Changes made by other
+    /// installs sharing the database are not seen. A receiver that falls more than
+    /// `EVENT_CAPACITY` events behind gets `RecvError::Lagged` and should reload.
+    pub fn subscribe(&self) -> broadcast::Receiver<AppEvent> {
+        self.events.subscribe()
+    }
+
+    fn publish(&self, event: AppEvent) {
+        // Nobody listening is not an error
+        let _ = self.events.send(event);
+    }
+
+    /// The database connection. Changes are attributed to the signed-in user.
+    async fn db(&self) -> RwLockReadGuard<'_, DbTool> {
+        self.db_manager.read().await
+    }
+
+    pub async fn authenticate_employee(
+        &self,
         employee_id: u16,
         password: &str,
     ) -> Result<bool, String> {
-        let employees = self
-            .employees
-            .lock()
-            .map_err(|_| "Failed to acquire lock".to_string())?;
+        // A new attempt ends the previous session, so nothing that follows a failed one is
+        // attributed to whoever was signed in before
+        self.logout().await;
+        let lockout = &self.config.security.lockout;
+        let now = utils::time::now();
+        if let Some(wait) = self.session.lock().await.attempts.retry_after(lockout, now) {
+            return Err(format!("Too many failed attempts, try again in {} seconds", wait));
+        }
 
-        employees
+        let employee = self.employees.read().await.get(&employee_id).cloned();
+        let Some(mut employee) = employee else {
+            self.session.lock().await.attempts.register_failure(lockout, now);
+            return Ok(false);
+        };
+
+        let mut record = self
+            .db()
+            .await
+            .database_get::<LoginRecord>(employee_id)
+            .await
+            .map_err(|_| "Failed to load login record".to_string())?
+            .unwrap_or_else(|| LoginRecord::new(employee_id));
+        if let Some(wait) = record.retry_after(lockout, now) {
+            return Err(format!("Account is locked, try again in {} seconds", wait));
+        }
+
+        let verified =
+            utils::security::verify_password(employee.password(), password).unwrap_or(false);
+        let redeemed = !verified && employee.redeem_reset_code(password);
+        if !verified && !redeemed {
+            self.session.lock().await.attempts.register_failure(lockout, now);
+            record.register_failure(lockout, now);
+            self.db()
+                .await
+                .database_upsert(&record)
+                .await
+                .map_err(|e| format!("Failed to save login record: {}", e))?;
+            return Ok(false);
+        }
+
+        {
+            let mut session = self.session.lock().await;
+            session.attempts.reset();
+            session.user = employee_id;
+            session.totp_enrolment = None;
+            session.totp_pending = employee.totp_secret().is_some()
+                || (self.config.security.totp_required_level != PermissionLevel::None
+                    && *employee.perm_level() >= self.config.security.totp_required_level);
+        }
+        self.db_manager.write().await.set_actor(Some(employee_id));
+        if record.failed_attempts() > 0 {
+            record.reset();
+            self.db()
+                .await
+                .database_upsert(&record)
+                .await
+                .map_err(|e| format!("Failed to save login record: {}", e))?;
+        }
+        if redeemed {
+            // The reset code is single use, so it has to be consumed before the login counts
+            self.save_employee(employee).await?;
+        } else if utils::security::needs_rehash(employee.password()) {
+            // The login already succeeded, so a failed upgrade is retried next time
+            employee.set_password(password.to_owned());
+            if let Err(e) = self.save_employee(employee).await {
+                eprintln!("{}", e);
+            }
+        }
+        Ok(true)
+    }
+
+    pub async fn logout(&self) {
+        let mut session = self.session.lock().await;
+        session.user = 0;
+        session.totp_pending = false;
+        session.totp_enrolment = None;
+        drop(session);
+        self.db_manager.write().await.set_actor(None);
+    }
+
+    /// Acts as an employee that a front end has already signed in, e.g. with a token that
+    /// `server` issued after a full `authenticate_employee`. The password is not checked
+    /// again, but the sign-in is refused once the account is locked or `stamp` no longer
+    /// matches its `session_stamp`.
+    pub async fn resume_session(&self, employee_id: u16, stamp: &str) -> Result<(), String> {
+        self.logout().await;
+        self.session.lock().await.user = employee_id;
+        self.db_manager.write().await.set_actor(Some(employee_id));
+        if let Err(e) = self.check_resumed_session(stamp).await {
+            self.logout().await;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    async fn check_resumed_session(&self, stamp: &str) -> Result<(), String> {
+        let employee = self.get_active_user().await?;
+        if employee.session_stamp() != stamp {
+            return Err("Account has changed, sign in again".to_string());
+        }
+        let locked = self
+            .db()
+            .await
+            .database_get::<LoginRecord>(employee.get_id())
+            .await
+            .map_err(|_| "Failed to load login record".to_string())?
+            .is_some_and(|record| record.is_locked(utils::time::now()));
+        if locked {
+            return Err("Account is locked".to_string());
+        }
+        Ok(())
+    }
+
+    /// The `session_stamp` of the signed-in user, to hand to `resume_session` later.
+    pub async fn session_stamp(&self) -> Result<String, String> {
+        Ok(self.get_active_user().await?.session_stamp())
+    }
+
+    /// Replaces the failed login count of this app's session and returns the old one.
+    /// `server` keeps a count per client and swaps it in around each login, so one client
+    /// cannot lock out the others.
+    pub async fn swap_login_attempts(&self, attempts: LoginRecord) -> LoginRecord {
+        std::mem::replace(&mut self.session.lock().await.attempts, attempts)
+    }
+
+    pub async fn totp_status(&self) -> TotpStatus {
+        if !self.session.lock().await.totp_pending {
+            return TotpStatus::Verified;
+        }
+        match self.get_current_user().await {
+            Ok(emp) if emp.totp_secret().is_some() => TotpStatus::CodeRequired,
+            _ => TotpStatus::EnrolmentRequired,
+        }
+    }
+
+    /// Completes a login with a code from the authenticator app or one of the recovery codes.
+    pub async fn verify_totp(&self, code: &str) -> Result<bool, String> {
+        let lockout = &self.config.security.lockout;
+        let now = utils::time::now();
+        if let Some(wait) = self.session.lock().await.attempts.retry_after(lockout, now) {
+            return Err(format!("Too many failed attempts, try again in {} seconds", wait));
+        }
+        let mut employee = self.get_current_user().await?;
+        let encrypted = employee
+            .totp_secret()
+            .ok_or_else(|| "Two-factor authentication is not set up".to_string())?;
+        let secret = utils::crypto::decrypt(self.get_totp_key()?, encrypted)?;
+
+        if let Some(step) = utils::totp::verify(&secret, code, now, employee.totp_last_step()) {
+            employee.set_totp_last_step(step);
+            self.save_employee(employee).await?;
+            self.session.lock().await.totp_pending = false;
+        } else if employee.redeem_recovery_code(code) {
+            self.save_employee(employee).await?;
+            self.session.lock().await.totp_pending = false;
+        } else {
+            self.session.lock().await.attempts.register_failure(lockout, now);
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    pub async fn begin_totp_enrolment(&self) -> Result<TotpEnrolment, String> {
+        let employee = self.get_current_user().await?;
+        self.get_totp_key()?;
+        let mut session = self.session.lock().await;
+        // Re-enrolling must not be a way around an outstanding code check
+        if session.totp_pending && employee.totp_secret().is_some() {
+            return Err("Two-factor authentication required".to_string());
+        }
+        let secret = utils::totp::generate_secret();
+        let enrolment = TotpEnrolment {
+            uri: utils::totp::provisioning_uri(&secret, employee.get_name(), "Rusty Library"),
+            secret: utils::totp::encode_secret(&secret),
+        };
+        session.totp_enrolment = Some(secret);
+        Ok(enrolment)
+    }
+
+    /// Saves the secret from `begin_totp_enrolment` once the user proves their app produces
+    /// matching codes. Returns recovery codes, which are only shown this once.
+    pub async fn confirm_totp_enrolment(&self, code: &str) -> Result<Vec<String>, String> {
+        let lockout = &self.config.security.lockout;
+        let now = utils::time::now();
+        let mut employee = self.get_current_user().await?;
+        let secret = {
+            let mut session = self.session.lock().await;
+            if let Some(wait) = session.attempts.retry_after(lockout, now) {
+                return Err(format!("Too many failed attempts, try again in {} seconds", wait));
+            }
+            let secret = session
+                .totp_enrolment
+                .clone()
+                .ok_or_else(|| "No enrolment in progress".to_string())?;
+            let Some(step) = utils::totp::verify(&secret, code, now, employee.totp_last_step())
+            else {
+                session.attempts.register_failure(lockout, now);
+                return Err("Invalid code".to_string());
+            };
+            employee.set_totp_last_step(step);
+            secret
+        };
+
+        let recovery_codes: Vec<String> =
+            (0..8).map(|_| utils::security::generate_code(10)).collect();
+        let encrypted = utils::crypto::encrypt(self.get_totp_key()?, &secret);
+        employee.set_totp(encrypted, &recovery_codes);
+        self.save_employee(employee).await?;
+        let mut session = self.session.lock().await;
+        session.totp_enrolment = None;
+        session.totp_pending = false;
+        Ok(recovery_codes)
+    }
+
+    fn get_totp_key(&self) -> Result<&[u8; 32], String> {
+        self.totp_key
+            .as_ref()
+            .ok_or_else(|| "The TOTP key is not configured".to_string())
+    }
+
+    pub async fn change_password(&self, old_password: &str, new_password: &str) -> Result<(), String> {
+        let mut employee = self.get_current_user().await?;
+        // After logging in with a reset code the old password is not known
+        if !employee.must_change_password()
+            && !utils::security::verify_password(employee.password(), old_password).unwrap_or(false)
+        {
+            return Err("Current password is incorrect".to_string());
+        }
+        employee.change_password(new_password, self.config.security.password_history)?;
+        self.save_employee(employee).await
+    }
+
+    pub async fn issue_reset_code(&self, employee_id: u16) -> Result<String, String> {
+        self.require_change_over(employee_id).await?;
+        let mut employee = self
+            .employees
+            .read()
+            .await
             .get(&employee_id)
-            .and_then(|emp| utils::security::verify_password(emp.password(), password).ok())
-            .map_or(Ok(false), |res| {
-                if res {
-                    self.user = employee_id;
-                }
-                Ok(res)
-            })
-            .or_else(|_: String| Err("Password verification failed".into()))
+            .cloned()
+            .ok_or_else(|| "User not found".to_string())?;
+        let code = employee.issue_reset_code()?;
+        self.save_employee(employee).await?;
+        Ok(code)
+    }
+
+    pub async fn password_change_required(&self) -> bool {
+        self.get_current_user()
+            .await
+            .is_ok_and(|emp| emp.must_change_password())
+    }
+
+    /// Stores an employee. Changes that only touch secrets, such as a used reset code or a
+    /// rehashed password, are not published.
+    async fn save_employee(&self, employee: Employee) -> Result<(), String> {
+        self.db()
+            .await
+            .database_update(&employee)
+            .await
+            .map_err(|e| format!("Failed to update on database: {}", e))?;
+        let id = employee.get_id();
+        let info = EmployeeInfo::from(&employee);
+        let previous = self.employees.write().await.insert(id, employee);
+        if previous.is_none_or(|stored| EmployeeInfo::from(&stored) != info) {
+            self.publish(AppEvent::EmployeeUpdated { id });
+        }
+        Ok(())
+    }
+
+    pub async fn unlock_employee(&self, employee_id: u16) -> Result<(), String> {
+        self.require_change_over(employee_id).await?;
+        self.db()
+            .await
+            .database_upsert(&LoginRecord::new(employee_id))
+            .await
+            .map_err(|e| format!("Failed to update on database: {}", e))
+    }
+
+    pub fn get_ui_config(&self) -> &UiConfig {
+        &self.config.ui
+    }
+
+    pub async fn store_config_secret(
+        &self,
+        section: &str,
+        setting: &str,
+        value: Option<&str>,
+    ) -> Result<(), String> {
+        self.require_permission(PermissionLevel::Admin).await?;
+        utils::loading::store_secret_setting(section, setting, value)
     }
 
-    pub fn get_permission_level(&self) -> Option<PermissionLevel> {
-        let employees = self.employees.lock().ok()?;
-        employees
-            .get(&self.user)
+    /// Names of the configured database profiles and the one currently in use.
+    pub async fn get_profiles(&self) -> Result<ProfileList, String> {
+        self.require_permission(PermissionLevel::Dev).await?;
+        Ok(ProfileList {
+            profiles: self.config.profiles.keys().cloned().collect(),
+            active: self.active_profile.read().await.clone(),
+        })
+    }
+
+    /// Reconnects to another configured database profile without restarting. Accounts
+    /// belong to a database, so the current user is logged out and has to sign in again
+    /// against the new profile.
+    pub async fn switch_profile(&self, name: &str) -> Result<(), String> {
+        self.require_permission(PermissionLevel::Dev).await?;
+        let settings = self.config.profile(name)?;
+        let db_manager = DbTool::new(settings)
+            .await
+            .map_err(|e| format!("Failed to connect to the database: {}", e))?;
+        // Check the tables before swapping, refresh_all_data cannot recover from a bad profile
+        db_manager
+            .count_rows::<Employee>()
+            .await
+            .map_err(|_| "Cannot read the Employee table".to_string())?;
+        db_manager
+            .count_rows::<Media>()
+            .await
+            .map_err(|_| "Cannot read the Media table".to_string())?;
+
+        eprintln!("Switching to database profile '{}'", name);
+        *self.db_manager.write().await = db_manager;
+        *self.active_profile.write().await = name.to_lowercase();
+        self.logout().await;
+        self.session.lock().await.attempts = LoginRecord::default();
+        self.trie.write().await.clear();
+        self.refresh_all_data().await
+    }
+
+    /// Allows Admins, and Managers acting on someone below them in the reporting tree.
+    async fn require_authority_over(&self, employee_id: u16) -> Result<(), String> {
+        let user = self.get_active_user().await?;
+        if utils::org::has_authority(&*self.employees.read().await, &user, employee_id) {
+            Ok(())
+        } else {
+            Err("Insufficient permissions".to_string())
+        }
+    }
+
+    /// Like `require_authority_over`, for changes to the employee's account. The employee
+    /// also has to rank below the current user.
+    async fn require_change_over(&self, employee_id: u16) -> Result<(), String> {
+        let user = self.get_active_user().await?;
+        if utils::org::can_change(&*self.employees.read().await, &user, employee_id) {
+            Ok(())
+        } else {
+            Err("Insufficient permissions".to_string())
+        }
+    }
+
+    async fn require_permission(&self, level: PermissionLevel) -> Result<(), String> {
+        let user = self.get_active_user().await?;
+        if *user.perm_level() >= level {
+            Ok(())
+        } else {
+            Err("Insufficient permissions".to_string())
+        }
+    }
+
+    pub async fn get_permission_level(&self) -> Option<PermissionLevel> {
+        let user = self.session.lock().await.user;
+        self.employees
+            .read()
+            .await
+            .get(&user)
             .map(|emp| emp.perm_level().to_owned())
     }
 
-    pub fn search_by_id<'a, T: DisplayInfo + ToString>(&self, search: &str) -> Option<Vec<String>> {
-        let name_vec: &Trie = self.trie.get(T::get_table_name())?;
+    pub async fn get_current_employee(&self) -> Result<EmployeeInfo, String> {
+        self.get_active_user().await.map(|emp| EmployeeInfo::from(&emp))
+    }
+
+    pub async fn get_employees(&self) -> Result<Vec<EmployeeInfo>, String> {
+        self.require_permission(PermissionLevel::Manager).await?;
+        let employees = self
+            .employees
+            .read().await;
+        Ok(employees.values().map(EmployeeInfo::from).collect())
+    }
+
+    pub async fn get_org_chart(&self) -> Result<OrgChart, String> {
+        self.require_permission(PermissionLevel::Manager).await?;
+        let employees = self
+            .employees
+            .read().await;
+        Ok(utils::org::build_chart(&employees))
+    }
+
+    /// The reports of an employee, or of the current user when `employee_id` is left out.
+    /// With `indirect` the reports of reports are included too.
+    pub async fn get_reports(
+        &self,
+        employee_id: Option<u16>,
+        indirect: bool,
+    ) -> Result<Vec<EmployeeInfo>, String> {
+        let current_user = self.get_active_user().await?;
+        let id = employee_id.unwrap_or(current_user.get_id());
+        if id != current_user.get_id() {
+            self.require_authority_over(id).await?;
+        }
+        let employees = self
+            .employees
+            .read().await;
+        let ids = if indirect {
+            utils::org::all_reports(&employees, id)
+        } else {
+            utils::org::direct_reports(&employees, id)
+        };
+        Ok(ids
+            .iter()
+            .filter_map(|id| employees.get(id))
+            .map(EmployeeInfo::from)
+            .collect())
+    }
+
+    /// The bosses above the current user, from their direct boss to the top.
+    pub async fn get_chain_of_command(&self) -> Result<Vec<EmployeeInfo>, String> {
+        let current_user = self.get_active_user().await?;
+        let employees = self
+            .employees
+            .read().await;
+        let chain = utils::org::chain_of_command(&employees, current_user.get_id())?;
+        Ok(chain
+            .iter()
+            .filter_map(|id| employees.get(id))
+            .map(EmployeeInfo::from)
+            .collect())
+    }
+
+    /// Lets a manager hand out budget to someone below them in the tree.
+    pub async fn set_alloc_budget(&self, employee_id: u16, alloc_budget: u16) -> Result<(), String> {
+        self.require_change_over(employee_id).await?;
+        let mut employee = self.get_employee(employee_id).await?;
+        employee.set_alloc_budget(alloc_budget);
+        self.save_employee(employee).await
+    }
+
+    pub async fn search_by_id<T: DisplayInfo + ToString>(&self, search: &str) -> Option<Vec<String>> {
+        let trie = self.trie.read().await;
+        let name_vec: &Trie = trie.get(T::get_table_name())?;
         name_vec.starts_with(search.into())
     }
 
-    fn update_data<T: DisplayInfo>(&mut self, data: Vec<T>, storage: &Arc<Mutex<HashMap<u16, T>>>) {
-        let mut storage_guard = storage.lock().unwrap_or_else(|e| e.into_inner());
+    async fn update_data<T: DisplayInfo>(&self, data: Vec<T>, storage: &RwLock<HashMap<u16, T>>) {
+        let mut storage_guard = storage.write().await;
         *storage_guard = utils::loading::vec_to_hashmap(data);
-        self.trie.insert(
+        self.trie.write().await.insert(
             T::get_table_name(),
             utils::loading::hashmap_to_trie(&storage_guard),
         );
     }
 
-    pub fn refresh_all_data(&mut self) {
-        // TODO: Redesign this so there is no need to temporarily take ownership of the Arc<Mutex<...>> fields
-        let temp_employees = mem::take(&mut self.employees);
-        let temp_media = mem::take(&mut self.media);
-        let emp_data = self.rt.block_on(self.db_manager.get_table::<Employee>());
-        let media_data = self.rt.block_on(self.db_manager.get_table::<Media>());
+    /// Finds media by name prefix, one copy per title, plus interlibrary loans that are on
+    /// site. Limited to the user's branch unless `all_branches` is set.
+    pub async fn search_media(&self, search: &str, all_branches: bool) -> Vec<SearchResult> {
+        // TODO: Change the logic so that it creates a new map with the name, that way we get O(nlogn) instead of O(n^2log(n))
+        let branch = self.get_branch_scope(all_branches).await;
+        let mut media_vec: Vec<Media> = vec![];
+
+        let names = self.search_by_id::<Media>(search).await.unwrap_or_default();
+        for med in self.get_media().await.values() {
+            let med_name = med.get_name().to_lowercase();
+            if names.iter().any(|n| n == &med_name)
+                && branch.is_none_or(|b| med.in_branch(b))
+                && !media_vec.iter().any(|m| m.get_name() == med_name)
+            {
+                media_vec.push(med.clone());
+            }
+        }
+        let mut results: Vec<SearchResult> = media_vec.into_iter().map(SearchResult::from).collect();
+        results.extend(
+            self.search_interlibrary_loans(search)
+                .await
+                .iter()
+                .filter(|loan| branch.is_none_or(|b| loan.in_branch(b)))
+                .map(SearchResult::from),
+        );
+        results
+    }
+
+    /// Reloads the cached tables. They are fetched concurrently and each one is swapped in
+    /// as a whole, so readers never see a half loaded table. The Employee and Media tables
+    /// are required, missing Branch and InterlibraryLoan tables are left empty so older
+    /// databases still start.
+    pub async fn refresh_all_data(&self) -> Result<(), String> {
+        let db = self.db().await;
+        let (emp_data, media_data, branch_data, loan_data) = tokio::join!(
+            db.get_table::<Employee>(),
+            db.get_table::<Media>(),
+            db.get_table::<Branch>(),
+            db.get_table::<InterlibraryLoan>(),
+        );
+        drop(db);
+        let emp_data = emp_data.map_err(|_| "Cannot read the Employee table".to_string())?;
+        let media_data = media_data.map_err(|_| "Cannot read the Media table".to_string())?;
+        let branch_data = optional_table(branch_data);
+        let loan_data = optional_table(loan_data);
+
+        self.update_data(emp_data, &self.employees).await;
+        self.update_data(media_data, &self.media).await;
+        self.update_data(branch_data, &self.branches).await;
+        self.update_data(loan_data, &self.interlibrary_loans).await;
+        self.publish(AppEvent::Reloaded);
+        Ok(())
+    }
+
+    /// Every row of the table for `T`, read from the database.
+    async fn load_table<T: DisplayInfo + serde::de::DeserializeOwned>(&self) -> Result<Vec<T>, String> {
+        self.db().await.get_table::<T>().await
+            .map_err(|_| format!("Cannot read the {} table", T::get_table_name()))
+    }
+
+    pub async fn rent_media(&self, media_id: u16) -> Result<(), String> {
+        let current_user = self.get_active_user().await?;
+        let loan_secs = self.config.loans.loan_days as u64 * 86_400;
+        let max_loans = self.config.loans.max_loans;
+        let media = self.change_media(media_id, AuditAction::Checkout, |media, catalogue| {
+            if !media.is_available() {
+                return Err(format!("{} is not available", media.get_name()));
+            }
+            let loans = catalogue
+                .values()
+                .filter(|m| m.renter_id() == Some(current_user.get_id()))
+                .count();
+            if loans >= max_loans as usize {
+                return Err(format!("Cannot borrow more than {} items at once", max_loans));
+            }
+            media.set_renter(&current_user);
+            media.set_due(utils::time::now() + loan_secs);
+            Ok(())
+        })
+        .await?;
+        self.publish(AppEvent::LoanCreated {
+            media_id,
+            renter: current_user.get_name().to_owned(),
+            due: media.due().unwrap_or_default(),
+        });
+        Ok(())
+    }
+
+    pub async fn return_media(&self, media_id: u16) -> Result<(), String> {
+        self.get_active_user().await?;
+        self.change_media(media_id, AuditAction::Return, |media, _| {
+            if media.renter().is_none() {
+                return Err(format!("{} is not checked out", media.get_name()));
+            }
+            media.clear_renter();
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_branches(&self) -> Result<Vec<Branch>, String> {
+        self.get_active_user().await?;
+        let branches = self
+            .branches
+            .read().await;
+        Ok(branches.values().cloned().collect())
+    }
+
+    pub async fn save_branch(&self, branch: Branch) -> Result<(), String> {
+        self.require_permission(PermissionLevel::Admin).await?;
+        branch.validate()?;
+        self.db().await.database_upsert(&branch).await
+            .map_err(|e| format!("Failed to update on database: {}", e))?;
+        self.cache_record(branch.get_id(), Some(branch)).await;
+        Ok(())
+    }
+
+    /// The branch that searches are limited to. Employees without a branch, and requests
+    /// for all branches, are not limited.
+    pub async fn get_branch_scope(&self, all_branches: bool) -> Option<u16> {
+        if all_branches {
+            return None;
+        }
+        self.get_current_user().await
+            .ok()
+            .map(|user| user.branch_id())
+            .filter(|&branch| branch != 0)
+    }
+
+    pub async fn get_transfers(&self, all_branches: bool) -> Result<Vec<Transfer>, String> {
+        self.get_active_user().await?;
+        let branch = self.get_branch_scope(all_branches).await;
+        let transfers = self.load_table::<Transfer>().await?;
+        Ok(transfers
+            .into_iter()
+            .filter(|transfer| branch.is_none_or(|b| transfer.involves(b)))
+            .collect())
+    }
+
+    /// Asks for a copy to be sent to another branch. It stays where it is until dispatched.
+    pub async fn request_transfer(
+        &self,
+        media_id: u16,
+        destination_branch: u16,
+    ) -> Result<Transfer, String> {
+        let current_user = self.get_active_user().await?;
+        let media = self.get_media_item(media_id).await?;
+        let known_branch = self
+            .branches
+            .read().await
+            .contains_key(&destination_branch);
+        if !known_branch {
+            return Err("Branch not found".to_string());
+        }
+        if media.branch_id() == destination_branch {
+            return Err(format!("{} is already at that branch", media.get_name()));
+        }
+        if media.in_transit() {
+            return Err(format!("{} is already in transit", media.get_name()));
+        }
+        // A second transfer would move the copy again once the first one is received
+        let open = self
+            .load_table::<Transfer>()
+            .await?
+            .into_iter()
+            .find(|transfer| transfer.media_id() == media_id && transfer.is_open());
+        if let Some(open) = open {
+            return Err(format!(
+                "{} already has an open transfer ({})",
+                media.get_name(),
+                open.get_id()
+            ));
+        }
+
+        let id = self.db().await.next_id::<Transfer>().await
+            .map_err(|e| e.to_string())?;
+        let transfer = Transfer::new(
+            id,
+            &media,
+            destination_branch,
+            current_user.get_id(),
+            utils::time::now(),
+        );
+        self.db().await.database_insert(&transfer).await
+            .map_err(|e| format!("Failed to create transfer: {}", e))?;
+        Ok(transfer)
+    }
+
+    /// Marks a requested transfer as sent. Only staff at the sending branch may do this.
+    pub async fn dispatch_transfer(&self, transfer_id: u16) -> Result<Transfer, String> {
+        self.advance_transfer(transfer_id, TransferStatus::Requested).await
+    }
+
+    /// Marks a transfer as arrived, moving the copy to its new branch. Only staff at the
+    /// receiving branch may do this.
+    pub async fn receive_transfer(&self, transfer_id: u16) -> Result<Transfer, String> {
+        self.advance_transfer(transfer_id, TransferStatus::InTransit).await
+    }
+
+    async fn advance_transfer(
+        &self,
+        transfer_id: u16,
+        expected: TransferStatus,
+    ) -> Result<Transfer, String> {
+        let current_user = self.get_active_user().await?;
+        let mut transfer = self.db().await.database_get::<Transfer>(transfer_id).await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "Transfer not found".to_string())?;
+        if transfer.status() != &expected {
+            return Err(format!("Transfer is not {:?}", expected));
+        }
+        let branch = match expected {
+            TransferStatus::Requested => transfer.source_branch(),
+            _ => transfer.destination_branch(),
+        };
+        let is_admin = *current_user.perm_level() >= PermissionLevel::Admin;
+        if current_user.branch_id() != branch && !is_admin {
+            return Err("Only staff at that branch can update this transfer".to_string());
+        }
+
+        let mut media = self.get_media_item(transfer.media_id()).await?;
+        let before = transfer.clone();
+        transfer.advance(&mut media, utils::time::now())?;
+        self.db().await.database_update(&transfer).await
+            .map_err(|e| format!("Failed to update on database: {}", e))?;
+        // Put the transfer back if the copy cannot be moved, so the two never disagree
+        if let Err(e) = self.save_media(media, AuditAction::Update).await {
+            self.db().await.database_update(&before).await
+                .map_err(|_| format!("{}, and transfer {} could not be restored", e, transfer_id))?;
+            return Err(e);
+        }
+        Ok(transfer)
+    }
+
+    pub async fn get_partner_libraries(&self) -> Result<Vec<PartnerLibrary>, String> {
+        self.get_active_user().await?;
+        self.load_table::<PartnerLibrary>().await
+    }
+
+    pub async fn save_partner_library(&self, partner: PartnerLibrary) -> Result<(), String> {
+        self.require_permission(PermissionLevel::Admin).await?;
+        partner.validate()?;
+        self.db().await.database_upsert(&partner).await
+            .map_err(|e| format!("Failed to update on database: {}", e))
+    }
+
+    /// Lists interlibrary loans, leaving out the ones already shipped back unless
+    /// `include_closed` is set.
+    pub async fn get_interlibrary_loans(
+        &self,
+        include_closed: bool,
+    ) -> Result<Vec<InterlibraryLoan>, String> {
+        self.get_active_user().await?;
+        let loans = self
+            .interlibrary_loans
+            .read().await;
+        Ok(loans
+            .values()
+            .filter(|loan| {
+                include_closed || loan.status() != &InterlibraryLoanStatus::ShippedBack
+            })
+            .cloned()
+            .collect())
+    }
+
+    /// Interlibrary loans whose title starts with `search`, while the item is on site.
+    pub async fn search_interlibrary_loans(&self, search: &str) -> Vec<InterlibraryLoan> {
+        let search = search.to_lowercase();
+        self.interlibrary_loans
+            .read()
+            .await
+            .values()
+            .filter(|loan| {
+                loan.is_on_site() && loan.get_name().to_lowercase().starts_with(&search)
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub async fn request_interlibrary_loan(
+        &self,
+        partner_id: u16,
+        title: String,
+        media_type: MediaType,
+        patron: Option<String>,
+    ) -> Result<InterlibraryLoan, String> {
+        let current_user = self.get_active_user().await?;
+        if title.trim().is_empty() {
+            return Err("A title is required".to_string());
+        }
+        self.db().await.database_get::<PartnerLibrary>(partner_id).await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "Partner library not found".to_string())?;
+        let id = self.db().await.next_id::<InterlibraryLoan>().await
+            .map_err(|e| e.to_string())?;
+        let loan = InterlibraryLoan::new(
+            id,
+            partner_id,
+            title,
+            media_type,
+            &current_user,
+            patron,
+            utils::time::now(),
+        );
+        self.db().await.database_insert(&loan).await
+            .map_err(|e| format!("Failed to create interlibrary loan: {}", e))?;
+        self.store_interlibrary_loan(loan).await
+    }
+
+    /// Moves a loan to its next status, see `InterlibraryLoan::advance`.
+    pub async fn advance_interlibrary_loan(
+        &self,
+        loan_id: u16,
+        partner_due: Option<u64>,
+        patron: Option<String>,
+    ) -> Result<InterlibraryLoan, String> {
+        self.get_active_user().await?;
+        let mut loan = self
+            .interlibrary_loans
+            .read().await
+            .get(&loan_id)
+            .cloned()
+            .ok_or_else(|| "Interlibrary loan not found".to_string())?;
+        let loan_secs = self.config.loans.loan_days as u64 * 86_400;
+        loan.advance(partner_due, patron, loan_secs, utils::time::now())?;
+        self.db().await.database_update(&loan).await
+            .map_err(|e| format!("Failed to update on database: {}", e))?;
+        self.store_interlibrary_loan(loan).await
+    }
+
+    async fn store_interlibrary_loan(
+        &self,
+        loan: InterlibraryLoan,
+    ) -> Result<InterlibraryLoan, String> {
+        self.interlibrary_loans
+            .write().await
+            .insert(loan.get_id(), loan.clone());
+        Ok(loan)
+    }
+
+    /// The allocated, committed and remaining budget of an employee. Managers may look at
+    /// their reports' budgets, everyone else only at their own.
+    pub async fn get_budget(&self, employee_id: Option<u16>) -> Result<BudgetSummary, String> {
+        let current_user = self.get_active_user().await?;
+        let employee = match employee_id {
+            Some(id) if id != current_user.get_id() => {
+                self.require_authority_over(id).await?;
+                self.get_employee(id).await?
+            }
+            _ => current_user,
+        };
+        let requests = self.load_table::<PurchaseRequest>().await?;
+        Ok(PurchaseRequest::budget(&employee, &requests))
+    }
+
+    /// The current user's own purchase requests and the ones waiting for their approval.
+    /// Admins see every request.
+    pub async fn get_purchase_requests(&self) -> Result<Vec<PurchaseRequest>, String> {
+        let current_user = self.get_active_user().await?;
+        let is_admin = *current_user.perm_level() >= PermissionLevel::Admin;
+        let requests = self.load_table::<PurchaseRequest>().await?;
+        Ok(requests
+            .into_iter()
+            .filter(|request| {
+                is_admin
+                    || request.requested_by() == current_user.get_id()
+                    || request.approver_id() == current_user.get_id()
+            })
+            .collect())
+    }
+
+    /// Asks the current user's manager to buy a title. The price has to fit in what is left
+    /// of the user's budget.
+    pub async fn request_purchase(
+        &self,
+        title: String,
+        media_type: MediaType,
+        vendor_id: u16,
+        price: u16,
+    ) -> Result<PurchaseRequest, String> {
+        let current_user = self.get_active_user().await?;
+        if title.trim().is_empty() {
+            return Err("A title is required".to_string());
+        }
+        let vendor = self.get_record::<Vendor>(vendor_id, "Vendor").await?;
+        let requests = self.load_table::<PurchaseRequest>().await?;
+        let budget = PurchaseRequest::budget(&current_user, &requests);
+        if (price as i64) > budget.remaining {
+            return Err(format!("Only {} of the budget is left", budget.remaining.max(0)));
+        }
+        let id = self.db().await.next_id::<PurchaseRequest>().await
+            .map_err(|e| e.to_string())?;
+        let request = PurchaseRequest::new(
+            id,
+            &current_user,
+            title,
+            media_type,
+            &vendor,
+            price,
+            utils::time::now(),
+        );
+        self.db().await.database_insert(&request).await
+            .map_err(|e| format!("Failed to create purchase request: {}", e))?;
+        Ok(request)
+    }
+
+    /// Approves or rejects a purchase. Only the requester's manager, or an Admin, may decide,
+    /// and nobody can approve their own request.
+    pub async fn decide_purchase(
+        &self,
+        request_id: u16,
+        approve: bool,
+    ) -> Result<PurchaseRequest, String> {
+        let current_user = self.get_active_user().await?;
+        let mut request = self.get_record::<PurchaseRequest>(request_id, "Purchase request").await?;
+        let is_admin = *current_user.perm_level() >= PermissionLevel::Admin;
+        if request.requested_by() == current_user.get_id() {
+            return Err("You cannot decide on your own purchase request".to_string());
+        }
+        if request.approver_id() != current_user.get_id() && !is_admin {
+            return Err("Only the requester's manager can decide on this request".to_string());
+        }
+        if approve {
+            let requester = self.get_employee(request.requested_by()).await?;
+            let requests = self.load_table::<PurchaseRequest>().await?;
+            let budget = PurchaseRequest::budget(&requester, &requests);
+            if (request.price() as i64) > budget.remaining {
+                return Err(format!(
+                    "The requester only has {} of their budget left",
+                    budget.remaining.max(0)
+                ));
+            }
+        }
+        request.decide(approve, utils::time::now())?;
+        self.db().await.database_update(&request).await
+            .map_err(|e| format!("Failed to update on database: {}", e))?;
+        Ok(request)
+    }
+
+    /// Records that an approved order has arrived and adds it to the catalogue at the
+    /// requester's branch.
+    pub async fn receive_purchase(&self, request_id: u16) -> Result<PurchaseRequest, String> {
+        let current_user = self.get_active_user().await?;
+        let mut request = self.get_record::<PurchaseRequest>(request_id, "Purchase request").await?;
+        let is_admin = *current_user.perm_level() >= PermissionLevel::Admin;
+        let involved = [request.requested_by(), request.approver_id()];
+        if !involved.contains(&current_user.get_id()) && !is_admin {
+            return Err("Only the requester or their manager can receive this order".to_string());
+        }
+        let requester = self.get_employee(request.requested_by()).await?;
+        let media_id = self.db().await.next_id::<Media>().await
+            .map_err(|e| e.to_string())?;
+        let approved = request.clone();
+        let media = request.receive(media_id, requester.branch_id())?;
+        // Marking the request received first means a second call cannot add the copy twice
+        self.db().await.database_update(&request).await
+            .map_err(|e| format!("Failed to update on database: {}", e))?;
+        if let Err(e) = self.db().await.database_insert(&media).await {
+            let error = format!("Failed to add media: {}", e);
+            self.db().await.database_update(&approved).await
+                .map_err(|_| format!("{}, and request {} could not be restored", error, request_id))?;
+            return Err(error);
+        }
+        if let Some(trie) = self.trie.write().await.get_mut(Media::get_table_name()) {
+            trie.insert(media.get_name().to_lowercase());
+        }
+        self.media.write().await.insert(media.get_id(), media.clone());
+        self.publish(AppEvent::MediaChanged { media });
+        Ok(request)
+    }
+
+    /// Committed purchase spending per department, or per project with `by_project`.
+    pub async fn get_spend_report(&self, by_project: bool) -> Result<Vec<SpendReport>, String> {
+        self.require_permission(PermissionLevel::Manager).await?;
+        let requests = self.load_table::<PurchaseRequest>().await?;
+        Ok(PurchaseRequest::spend_report(&requests, by_project))
+    }
+
+    pub async fn get_vendors(&self) -> Result<Vec<Vendor>, String> {
+        self.get_active_user().await?;
+        self.load_table::<Vendor>().await
+    }
+
+    /// Creates or updates a vendor. Media from the vendor pick up a changed name.
+    pub async fn save_vendor(&self, vendor: Vendor) -> Result<(), String> {
+        self.require_permission(PermissionLevel::Admin).await?;
+        vendor.validate()?;
+        self.db().await.database_upsert(&vendor).await
+            .map_err(|e| format!("Failed to update on database: {}", e))?;
+        let renamed: Vec<Media> = self
+            .get_media()
+            .await
+            .values()
+            .filter(|m| m.vendor_id() == Some(vendor.get_id()) && m.vendor() != vendor.get_name())
+            .cloned()
+            .collect();
+        for mut media in renamed {
+            media.assign_vendor(&vendor);
+            self.save_media(media, AuditAction::Update).await?;
+        }
+        Ok(())
+    }
+
+    /// One-time migration from the free-text `Media.vendor` to vendor records, see
+    /// `Vendor::merge_free_text`. Media that already point at a vendor are left alone, so
+    /// running it again only picks up new free-text values.
+    pub async fn migrate_vendors(&self) -> Result<VendorMerge, String> {
+        self.require_permission(PermissionLevel::Admin).await?;
+        let mut vendors = self.load_table::<Vendor>().await?;
+        let next_id = self.db().await.next_id::<Vendor>().await
+            .map_err(|e| e.to_string())?;
+        let media = utils::loading::hashmap_to_vec(&*self.get_media().await);
+        let merge = Vendor::merge_free_text(&vendors, &media, next_id);
+
+        for vendor in &merge.created {
+            self.db().await.database_insert(vendor).await
+                .map_err(|e| format!("Failed to create vendor {}: {}", vendor.get_name(), e))?;
+        }
+        vendors.extend(merge.created.iter().cloned());
+        for &(media_id, vendor_id) in &merge.assignments {
+            let vendor = vendors
+                .iter()
+                .find(|vendor| vendor.get_id() == vendor_id)
+                .ok_or_else(|| "Vendor not found".to_string())?;
+            let mut media = self.get_media_item(media_id).await?;
+            media.assign_vendor(vendor);
+            self.save_media(media, AuditAction::Update).await?;
+        }
+        Ok(merge)
+    }
 
-        self.update_data(emp_data, &temp_employees);
-        self.update_data(media_data, &temp_media);
-        self.employees = temp_employees;
-        self.media = temp_media;
+    pub async fn get_vendor_report(&self) -> Result<Vec<VendorReport>, String> {
+        self.require_permission(PermissionLevel::Manager).await?;
+        let vendors = self.load_table::<Vendor>().await?;
+        let requests = self.load_table::<PurchaseRequest>().await?;
+        let media = utils::loading::hashmap_to_vec(&*self.get_media().await);
+        Ok(Vendor::report(&vendors, &media, &requests))
     }
 
-    pub async fn rent_media(&mut self, media_id: u16) -> Result<(), String> {
-        let current_user = self.get_current_user()?;
-        let media_guard = self.media.lock().map_err(|_| "Failed to acquire lock")?;
-        let media = media_guard
+    pub async fn get_departments(&self) -> Result<Vec<Department>, String> {
+        self.get_active_user().await?;
+        self.load_table::<Department>().await
+    }
+
+    /// Creates or updates a department. Employees in it pick up a changed name.
+    pub async fn save_department(&self, department: Department) -> Result<(), String> {
+        self.require_permission(PermissionLevel::Admin).await?;
+        department.validate()?;
+        self.db().await.database_upsert(&department).await
+            .map_err(|e| format!("Failed to update on database: {}", e))?;
+        for mut employee in self.find_employees(|emp| {
+            emp.department_id() == Some(department.get_id())
+                && emp.department() != department.get_name()
+        }).await? {
+            employee.assign_department(Some(&department));
+            self.save_employee(employee).await?;
+        }
+        Ok(())
+    }
+
+    /// Deletes a department that no employee or project belongs to.
+    pub async fn delete_department(&self, department_id: u16) -> Result<(), String> {
+        self.require_permission(PermissionLevel::Admin).await?;
+        let department = self.get_record::<Department>(department_id, "Department").await?;
+        if !self.find_employees(|emp| emp.department_id() == Some(department_id)).await?.is_empty() {
+            return Err("Employees still belong to this department".to_string());
+        }
+        let projects = self.load_table::<Project>().await?;
+        if projects.iter().any(|p| p.department_id() == Some(department_id)) {
+            return Err("Projects still belong to this department".to_string());
+        }
+        self.db().await.database_delete(&department).await
+            .map_err(|e| format!("Failed to delete from database: {}", e))
+    }
+
+    pub async fn get_projects(&self) -> Result<Vec<Project>, String> {
+        self.get_active_user().await?;
+        self.load_table::<Project>().await
+    }
+
+    /// Creates or updates a project. Employees on it pick up a changed name.
+    pub async fn save_project(&self, project: Project) -> Result<(), String> {
+        self.require_permission(PermissionLevel::Admin).await?;
+        project.validate()?;
+        if let Some(department_id) = project.department_id() {
+            self.get_record::<Department>(department_id, "Department").await?;
+        }
+        self.db().await.database_upsert(&project).await
+            .map_err(|e| format!("Failed to update on database: {}", e))?;
+        for mut employee in self.find_employees(|emp| {
+            emp.project_id() == Some(project.get_id()) && emp.project() != project.get_name()
+        }).await? {
+            employee.assign_project(Some(&project));
+            self.save_employee(employee).await?;
+        }
+        Ok(())
+    }
+
+    /// Deletes a project nobody is working on.
+    pub async fn delete_project(&self, project_id: u16) -> Result<(), String> {
+        self.require_permission(PermissionLevel::Admin).await?;
+        let project = self.get_record::<Project>(project_id, "Project").await?;
+        if !self.find_employees(|emp| emp.project_id() == Some(project_id)).await?.is_empty() {
+            return Err("Employees are still working on this project".to_string());
+        }
+        self.db().await.database_delete(&project).await
+            .map_err(|e| format!("Failed to delete from database: {}", e))
+    }
+
+    /// Moves an employee to another department and project. `None` clears the assignment.
+    /// Managers can only move employees below them in the tree and in rank.
+    pub async fn assign_employee(
+        &self,
+        employee_id: u16,
+        department_id: Option<u16>,
+        project_id: Option<u16>,
+    ) -> Result<(), String> {
+        self.require_change_over(employee_id).await?;
+        let department = self.resolve::<Department>(department_id).await?;
+        let project = self.resolve::<Project>(project_id).await?;
+        let mut employee = self.get_employee(employee_id).await?;
+        employee.assign_department(department.as_ref());
+        employee.assign_project(project.as_ref());
+        self.save_employee(employee).await
+    }
+
+    /// One-time migration from the free-text `Employee.department` and `project` to
+    /// department and project records. Names are matched ignoring case and extra spaces, and
+    /// employees already linked to a record are left alone.
+    pub async fn migrate_departments(&self) -> Result<RegistryMigration, String> {
+        self.require_permission(PermissionLevel::Admin).await?;
+        let employees: Vec<Employee> = self.find_employees(|_| true).await?;
+        let departments = self.load_table::<Department>().await?;
+        let projects = self.load_table::<Project>().await?;
+        let next_department = self.db().await.next_id::<Department>().await
+            .map_err(|e| e.to_string())?;
+        let next_project = self.db().await.next_id::<Project>().await
+            .map_err(|e| e.to_string())?;
+
+        let department_merge = utils::migrate::merge_names(
+            &departments.iter().map(|d| (d.get_id(), d.get_name())).collect::<Vec<_>>(),
+            &employees
+                .iter()
+                .filter(|emp| emp.department_id().is_none())
+                .map(|emp| (emp.get_id(), emp.department()))
+                .collect::<Vec<_>>(),
+            next_department,
+            utils::migrate::normalize_whitespace,
+        );
+        let project_merge = utils::migrate::merge_names(
+            &projects.iter().map(|p| (p.get_id(), p.get_name())).collect::<Vec<_>>(),
+            &employees
+                .iter()
+                .filter(|emp| emp.project_id().is_none())
+                .map(|emp| (emp.get_id(), emp.project()))
+                .collect::<Vec<_>>(),
+            next_project,
+            utils::migrate::normalize_whitespace,
+        );
+
+        let mut departments = departments;
+        for (id, name) in &department_merge.created {
+            let department = Department::new(*id, name.clone());
+            self.db().await.database_insert(&department).await
+                .map_err(|e| format!("Failed to create department {}: {}", name, e))?;
+            departments.push(department);
+        }
+        let mut projects = projects;
+        let now = utils::time::now();
+        for (id, name) in &project_merge.created {
+            let project = Project::new(*id, name.clone(), now, None, 0);
+            self.db().await.database_insert(&project).await
+                .map_err(|e| format!("Failed to create project {}: {}", name, e))?;
+            projects.push(project);
+        }
+
+        let mut employees: HashMap<u16, Employee> = employees
+            .into_iter()
+            .map(|emp| (emp.get_id(), emp))
+            .collect();
+        let mut changed: Vec<u16> = vec![];
+        for &(employee_id, department_id) in &department_merge.assignments {
+            if let Some(employee) = employees.get_mut(&employee_id) {
+                let department = departments.iter().find(|d| d.get_id() == department_id);
+                employee.assign_department(department);
+                changed.push(employee_id);
+            }
+        }
+        for &(employee_id, project_id) in &project_merge.assignments {
+            if let Some(employee) = employees.get_mut(&employee_id) {
+                let project = projects.iter().find(|p| p.get_id() == project_id);
+                employee.assign_project(project);
+                changed.push(employee_id);
+            }
+        }
+        changed.sort_unstable();
+        changed.dedup();
+        let employees_updated = changed.len();
+        for employee_id in changed {
+            if let Some(employee) = employees.remove(&employee_id) {
+                self.save_employee(employee).await?;
+            }
+        }
+        Ok(RegistryMigration {
+            departments_created: department_merge.created.len(),
+            projects_created: project_merge.created.len(),
+            employees_updated,
+        })
+    }
+
+    pub async fn get_project_spending(&self) -> Result<Vec<ProjectSpend>, String> {
+        self.require_permission(PermissionLevel::Manager).await?;
+        let projects = self.load_table::<Project>().await?;
+        let requests = self.load_table::<PurchaseRequest>().await?;
+        Ok(Project::spending(&projects, &requests))
+    }
+
+    async fn find_employees(&self, filter: impl Fn(&Employee) -> bool) -> Result<Vec<Employee>, String> {
+        let employees = self
+            .employees
+            .read().await;
+        Ok(employees.values().filter(|emp| filter(emp)).cloned().collect())
+    }
+
+    async fn get_record<T: DisplayInfo + serde::de::DeserializeOwned>(
+        &self,
+        id: u16,
+        kind: &str,
+    ) -> Result<T, String> {
+        self.db().await.database_get::<T>(id).await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("{} not found", kind))
+    }
+
+    async fn get_employee(&self, employee_id: u16) -> Result<Employee, String> {
+        self.employees
+            .read().await
+            .get(&employee_id)
+            .cloned()
+            .ok_or_else(|| "Employee not found".to_string())
+    }
+
+    pub async fn get_audit_log(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>, String> {
+        self.require_permission(PermissionLevel::Admin).await?;
+        self.db().await.get_audit_log(filter).await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn get_media_item(&self, media_id: u16) -> Result<Media, String> {
+        self.media
+            .read().await
             .get(&media_id)
-            .ok_or_else(|| "Media not found".to_string())?;
-        let mut media = media.clone();
-        media.set_renter(current_user.get_name().to_owned());
-        self.db_manager.database_update(&media).await
-            .map_err(|_| "Failed to update on database".to_string())?;
+            .cloned()
+            .ok_or_else(|| "Media not found".to_string())
+    }
+
+    async fn save_media(&self, media: Media, action: AuditAction) -> Result<(), String> {
+        self.db()
+            .await
+            .database_update_as(&media, action)
+            .await
+            .map_err(|e| format!("Failed to update on database: {}", e))?;
+        self.media.write().await.insert(media.get_id(), media.clone());
+        self.publish(AppEvent::MediaChanged { media });
         Ok(())
     }
 
-    fn get_current_user(&self) -> Result<Employee, String> {
+    /// Checks and changes a copy while holding the catalogue lock until it is saved, so two
+    /// concurrent checkouts of the same copy cannot both succeed. `change` also sees the rest
+    /// of the catalogue. Returns the changed copy.
+    async fn change_media(
+        &self,
+        media_id: u16,
+        action: AuditAction,
+        change: impl FnOnce(&mut Media, &HashMap<u16, Media>) -> Result<(), String> + Send,
+    ) -> Result<Media, String> {
+        let mut catalogue = self.media.write().await;
+        let mut media = catalogue
+            .get(&media_id)
+            .cloned()
+            .ok_or_else(|| "Media not found".to_string())?;
+        change(&mut media, &catalogue)?;
+        self.db()
+            .await
+            .database_update_as(&media, action)
+            .await
+            .map_err(|e| format!("Failed to update on database: {}", e))?;
+        catalogue.insert(media_id, media.clone());
+        drop(catalogue);
+        self.publish(AppEvent::MediaChanged { media: media.clone() });
+        Ok(media)
+    }
+
+    async fn get_current_user(&self) -> Result<Employee, String> {
+        let user = self.session.lock().await.user;
         self.employees
-            .lock()
-            .map_err(|_| "Failed to acquire lock".to_string())?
-            .get(&self.user)
+            .read()
+            .await
+            .get(&user)
             .cloned()
             .ok_or_else(|| "User not found".to_string())
     }
 
-    fn create_obj<T: DisplayInfo + Default + serde::Serialize + serde::de::DeserializeOwned>(
+    /// Like `get_current_user`, but refuses users that have not finished logging in or still
+    /// have to change their password.
+    async fn get_active_user(&self) -> Result<Employee, String> {
+        let user = self.get_current_user().await?;
+        if self.session.lock().await.totp_pending {
+            return Err("Two-factor authentication required".to_string());
+        }
+        if user.must_change_password() {
+            return Err("Password change required".to_string());
+        }
+        Ok(user)
+    }
+
+    /// Creates a record from `values`, given as (field, value) and read as the schema says.
+    /// Editable fields that are left out are prompted for on stdin when `interactive` is set,
+    /// where an empty answer keeps the default.
+    pub async fn create_obj<T: Record>(
         &self,
-    ) -> Result<(), String> {
-        let mut json_obj = serde_json::to_value(T::default()).unwrap();
-        let keys: Vec<String> = json_obj.as_object().unwrap().keys().cloned().collect();
-        for key in &keys {
-            println!("Enter a new value for {}", key);
-            let input = utils::user::get_input();
-            let original_value = &json_obj[&key];
-            let new_value = match original_value {
-                Value::Bool(_) => Value::Bool(
-                    input
-                        .parse()
-                        .map_err(|_| format!("Expected a boolean value for {}", key))?,
-                ),
-                Value::Number(_) => Value::Number(
-                    input
-                        .parse()
-                        .map_err(|_| format!("Expected a numeric value for {}", key))?,
-                ),
-                _ => Value::String(input),
-            };
-            json_obj[&key] = new_value;
+        values: &[(String, String)],
+        interactive: bool,
+    ) -> Result<T, String> {
+        self.require_permission(PermissionLevel::Admin).await?;
+        let mut fields = parse_fields::<T>(values)?;
+        if interactive {
+            for field in T::schema() {
+                if field.read_only || fields.contains_key(field.name) {
+                    continue;
+                }
+                let input = prompt_for(&field);
+                if input.is_empty() {
+                    continue;
+                }
+                let value = field
+                    .parse(&input)
+                    .map_err(|e| format!("{} for {}", e, field.name))?;
+                fields.insert(field.name.to_string(), value);
+            }
         }
-        let mut obj: T = serde_json::from_value(json_obj).map_err(|e| e.to_string())?;
+        self.create_from(fields).await
+    }
+
+    /// Inserts a record made of payload fields on top of the defaults, under the next free
+    /// id. New employees have no password, hand them a code with `issue_reset_code`.
+    async fn create_from<T: Record>(&self, fields: Map<String, Value>) -> Result<T, String> {
+        let id = self.db().await.next_id::<T>().await.map_err(|e| e.to_string())?;
+        let mut defaults = serde_json::to_value(T::default()).map_err(|e| e.to_string())?;
+        defaults["id"] = id.into();
+        let defaults: T = serde_json::from_value(defaults).map_err(|e| e.to_string())?;
+
+        let mut obj = merge_fields(&defaults, &fields)?;
+        obj.validate()?;
         obj.additional_setup();
-        self.rt
-            .block_on(self.db_manager.database_insert(&obj))
-            .map_err(|_| "Failed to update on database".to_string())?;
-        Ok(())
+        T::save(self, obj.clone(), true).await?;
+        Ok(obj)
+    }
+
+    pub async fn get_obj<T: DisplayInfo + serde::de::DeserializeOwned>(&self, id: u16) -> Result<T, String> {
+        self.require_permission(PermissionLevel::Admin).await?;
+        self.get_record::<T>(id, T::get_table_name()).await
+    }
+
+    /// Lists the records of a table and lets the user pick one by id on stdin.
+    pub async fn select_obj<T: DisplayInfo + ToString + Clone + serde::de::DeserializeOwned>(
+        &self,
+    ) -> Result<Option<T>, String> {
+        self.require_permission(PermissionLevel::Admin).await?;
+        let items = utils::loading::vec_to_hashmap(self.load_table::<T>().await?);
+        println!("Enter the id of an item");
+        Ok(self.list_items(&items).cloned())
     }
 
     fn list_items<'a, T: DisplayInfo + ToString>(
@@ -174,41 +1482,569 @@ impl App {
         items.get(&response)
     }
 
-    fn update_item<T: DisplayInfo>(&self, obj: &T) -> Result<(), &str> {
-        let mut json_obj = serde_json::to_value(obj).unwrap();
-        let keys: Vec<String> = json_obj.as_object().unwrap().keys().cloned().collect();
+    /// Changes the record with `id`. Without `values` the field to change is picked on stdin.
+    pub async fn update_obj<T: Record>(
+        &self,
+        id: u16,
+        values: &[(String, String)],
+    ) -> Result<T, String> {
+        self.require_permission(PermissionLevel::Admin).await?;
+        let obj = self.get_record::<T>(id, T::get_table_name()).await?;
+
+        let mut values = values.to_vec();
+        if values.is_empty() {
+            let schema = T::schema();
+            let editable: Vec<&FieldSchema> = schema.iter().filter(|f| !f.read_only).collect();
+            println!("Enter the value that you want to change");
+            for (key, field) in editable.iter().enumerate() {
+                println!("{} - {}", key + 1, field.name)
+            }
 
-        println!("Enter the value that you want to change");
-        for (key, value) in json_obj.as_object().unwrap().keys().enumerate() {
-            println!("{} - {}", key + 1, value)
+            let response: usize = match utils::user::get_input().trim().parse() {
+                Ok(num) => num,
+                Err(_) => return Err("Invalid number".to_string()),
+            };
+            let field = editable
+                .get(response.wrapping_sub(1))
+                .ok_or_else(|| "Invalid number".to_string())?;
+            values.push((field.name.to_string(), prompt_for(field)));
         }
 
-        let response: usize = match utils::user::get_input().trim().parse() {
-            Ok(num) => num,
-            Err(_) => return Err("Invalid number"),
+        let changes = parse_fields::<T>(&values)?;
+        self.apply_changes(&obj, changes).await
+    }
+
+    /// Changes fields of the record with `id`, given as JSON values.
+    pub async fn patch_obj<T: Record>(
+        &self,
+        id: u16,
+        changes: Map<String, Value>,
+    ) -> Result<T, String> {
+        self.require_permission(PermissionLevel::Admin).await?;
+        let obj = self.get_record::<T>(id, T::get_table_name()).await?;
+        self.apply_changes(&obj, changes).await
+    }
+
+    async fn apply_changes<T: Record>(
+        &self,
+        obj: &T,
+        changes: Map<String, Value>,
+    ) -> Result<T, String> {
+        let updated_obj = merge_fields(obj, &changes)?;
+        updated_obj.validate()?;
+        T::save(self, updated_obj.clone(), false).await?;
+        Ok(updated_obj)
+    }
+
+    pub async fn delete_obj<T: Record>(&self, id: u16) -> Result<(), String> {
+        self.require_permission(PermissionLevel::Admin).await?;
+        T::delete(self, id).await
+    }
+
+    /// Stores a checked record and keeps the cached copy of its table in step.
+    async fn write_obj<T: Record>(&self, obj: T, created: bool) -> Result<(), String> {
+        let db = self.db().await;
+        let written = if created {
+            db.database_insert(&obj).await
+        } else {
+            db.database_update(&obj).await
         };
-        let field_name = &keys[response - 1];
-        println!("Enter a new value for {}", field_name);
+        drop(db);
+        written.map_err(|e| format!("Failed to update on database: {}", e))?;
+        self.cache_record(obj.get_id(), Some(obj)).await;
+        Ok(())
+    }
 
-        let new_value = utils::user::get_input().trim().to_string();
-        json_obj[field_name] = Value::String(new_value);
+    async fn remove_obj<T: Record>(&self, id: u16) -> Result<(), String> {
+        let obj = self.get_record::<T>(id, T::get_table_name()).await?;
+        self.delete_item(&obj).await.map_err(|e| e.to_string())?;
+        self.cache_record::<T>(id, None).await;
+        Ok(())
+    }
 
-        let mut updated_obj: T =
-            serde_json::from_value(json_obj).map_err(|_| "Failed to update object")?;
-        updated_obj.additional_setup();
+    /// Puts a written record into the cached copy of its table, or takes it out for `None`,
+    /// rebuilds the table's search trie and publishes the change. Tables that are not cached
+    /// are left alone.
+    async fn cache_record<T: Record>(&self, id: u16, record: Option<T>) {
+        let Some(storage) = T::cache(self) else {
+            return;
+        };
+        let event = T::changed(id, record.as_ref());
+        let mut storage_guard = storage.write().await;
+        match record {
+            Some(record) => storage_guard.insert(id, record),
+            None => storage_guard.remove(&id),
+        };
+        self.trie.write().await.insert(
+            T::get_table_name(),
+            utils::loading::hashmap_to_trie(&storage_guard),
+        );
+        drop(storage_guard);
+        if let Some(event) = event {
+            self.publish(event);
+        }
+    }
 
-        self.rt
-            .block_on(self.db_manager.database_update(&updated_obj))
-            .map_err(|_| "Failed to update on database")?;
+    async fn delete_item<T: DisplayInfo>(&self, item: &T) -> Result<(), DbToolError> {
+        self.db().await.database_delete(item).await?;
         Ok(())
     }
 
-    fn delete_item<T: DisplayInfo>(&self, item: &T) -> Result<(), DbToolError> {
-        self.rt.block_on(self.db_manager.database_delete(item))?;
+    /// Every row of a table, as stored. Employee rows include password hashes.
+    pub async fn export_table<T: DisplayInfo + serde::de::DeserializeOwned>(
+        &self,
+    ) -> Result<Vec<T>, String> {
+        self.require_permission(PermissionLevel::Admin).await?;
+        self.load_table::<T>().await
+    }
+
+    /// Inserts or overwrites rows, e.g. from `export_table`. Values are stored as given, so
+    /// passwords have to be hashes already. Every row is checked before any is written.
+    /// Returns how many rows were written.
+    pub async fn import_table<T: Record>(&self, items: &[T]) -> Result<usize, String> {
+        self.require_permission(PermissionLevel::Admin).await?;
+        T::check_import(self, items).await?;
+        for item in items {
+            self.db().await.database_upsert(item).await
+                .map_err(|e| format!("Failed to import {}: {}", item.get_id(), e))?;
+        }
+        self.refresh_all_data().await?;
+        Ok(items.len())
+    }
+
+    /// The rows of a registered table, by id. Employees are listed without their secrets.
+    pub async fn list_records(&self, table: Table) -> Result<Vec<Value>, String> {
+        self.require_permission(table.read_level()).await?;
+        Ok(with_record!(table, T => {
+            let mut records = self.load_records::<T>().await?;
+            records.sort_by_key(|record| record.get_id());
+            records.iter().map(Record::view).collect()
+        }))
+    }
+
+    pub async fn read_record(&self, table: Table, id: u16) -> Result<Value, String> {
+        self.require_permission(table.read_level()).await?;
+        with_record!(table, T => self.load_record::<T>(id).await.map(|record| record.view()))
+    }
+
+    /// Creates a record from a JSON payload. Fields that are left out keep their defaults,
+    /// and the id is assigned.
+    pub async fn create_record(
+        &self,
+        table: Table,
+        fields: Map<String, Value>,
+    ) -> Result<Value, String> {
+        self.require_permission(PermissionLevel::Admin).await?;
+        with_record!(table, T => self.create_from::<T>(fields).await.map(|record| record.view()))
+    }
+
+    /// Changes fields of a record, given as JSON values.
+    pub async fn update_record(
+        &self,
+        table: Table,
+        id: u16,
+        changes: Map<String, Value>,
+    ) -> Result<Value, String> {
+        self.require_permission(PermissionLevel::Admin).await?;
+        with_record!(table, T => self.patch_obj::<T>(id, changes).await.map(|record| record.view()))
+    }
+
+    /// Describes the fields of a table, so front ends can build forms for it.
+    pub async fn get_table_schema(&self, table: Table) -> Result<TableSchema, String> {
+        self.require_permission(table.read_level()).await?;
+        Ok(table.schema())
+    }
+
+    pub async fn delete_record(&self, table: Table, id: u16) -> Result<(), String> {
+        with_record!(table, T => self.delete_obj::<T>(id).await)
+    }
+
+    async fn load_records<T: Record>(&self) -> Result<Vec<T>, String> {
+        match T::cache(self) {
+            Some(storage) => Ok(storage.read().await.values().cloned().collect()),
+            None => self.load_table::<T>().await,
+        }
+    }
+
+    async fn load_record<T: Record>(&self, id: u16) -> Result<T, String> {
+        let cached = match T::cache(self) {
+            Some(storage) => storage.read().await.get(&id).cloned(),
+            None => None,
+        };
+        match cached {
+            Some(record) => Ok(record),
+            None => self.get_record::<T>(id, T::get_table_name()).await,
+        }
+    }
+
+    /// Writes an employee for the generic record methods. Only employees ranked below the
+    /// current user can be written, before and after the change, so nobody can raise an
+    /// account to their own rank or take over one at or above it. References that changed
+    /// have to exist, and the department and project names are copied from their records.
+    async fn save_employee_record(
+        &self,
+        mut employee: Employee,
+        created: bool,
+    ) -> Result<(), String> {
+        let user = self.get_active_user().await?;
+        let stored = self.employees.read().await.get(&employee.get_id()).cloned();
+        let outranked = |employee: &Employee| employee.perm_level() >= user.perm_level();
+        if outranked(&employee) || (!created && stored.as_ref().is_none_or(outranked)) {
+            return Err("You can only write employees ranked below you".to_string());
+        }
+
+        let stored = stored.filter(|_| !created);
+        let changed = |value: fn(&Employee) -> Option<u16>| {
+            stored.as_ref().is_none_or(|stored| value(stored) != value(&employee))
+        };
+        let [boss_changed, branch_changed, department_changed, project_changed] = [
+            changed(|e| Some(e.boss_id())),
+            changed(|e| Some(e.branch_id())),
+            changed(Employee::department_id),
+            changed(Employee::project_id),
+        ];
+        let boss_id = employee.boss_id();
+        if boss_changed
+            && boss_id != 0
+            && boss_id != employee.get_id()
+            && !self.employees.read().await.contains_key(&boss_id)
+        {
+            return Err(format!("Employee {} not found", boss_id));
+        }
+        if branch_changed {
+            self.require_branch(employee.branch_id()).await?;
+        }
+        // Rows that only have the free-text names keep them until an id is set
+        if department_changed {
+            let department = self.resolve::<Department>(employee.department_id()).await?;
+            employee.assign_department(department.as_ref());
+        }
+        if project_changed {
+            let project = self.resolve::<Project>(employee.project_id()).await?;
+            employee.assign_project(project.as_ref());
+        }
+        self.write_obj(employee, created).await
+    }
+
+    /// Refuses imported employee rows ranked at or above the current user, and rows that
+    /// would overwrite such an account, like `save_employee_record` does for single writes.
+    async fn check_employee_import(&self, employees: &[Employee]) -> Result<(), String> {
+        let user = self.get_active_user().await?;
+        let stored = self.employees.read().await;
+        let outranked = |employee: &Employee| employee.perm_level() >= user.perm_level();
+        for employee in employees {
+            if outranked(employee) || stored.get(&employee.get_id()).is_some_and(outranked) {
+                return Err(format!(
+                    "Failed to import {}: you can only write employees ranked below you",
+                    employee.get_id()
+                ));
+            }
+        }
         Ok(())
     }
 
-    pub fn get_media(&self) -> std::sync::MutexGuard<HashMap<u16, Media>> {
-        self.media.lock().expect("Failed to lock media mutex")
+    /// Writes media for the generic record methods. The branch has to exist, and the vendor
+    /// name is copied from the vendor record when `vendor_id` changes.
+    async fn save_media_record(&self, mut media: Media, created: bool) -> Result<(), String> {
+        let stored = match created {
+            true => None,
+            false => self.media.read().await.get(&media.get_id()).cloned(),
+        };
+        if stored.as_ref().is_none_or(|stored| stored.branch_id() != media.branch_id()) {
+            self.require_branch(media.branch_id()).await?;
+        }
+        if stored.as_ref().is_none_or(|stored| stored.vendor_id() != media.vendor_id()) {
+            match self.resolve::<Vendor>(media.vendor_id()).await? {
+                Some(vendor) => media.assign_vendor(&vendor),
+                None if stored.is_some() => media.set_vendor(String::new()),
+                None => {}
+            }
+        }
+        self.write_obj(media, created).await
+    }
+
+    /// Loads the record that an optional reference points at, refusing ids that do not exist.
+    async fn resolve<T: DisplayInfo + serde::de::DeserializeOwned>(
+        &self,
+        id: Option<u16>,
+    ) -> Result<Option<T>, String> {
+        match id {
+            Some(id) => {
+                let kind = format!("{} {}", T::get_table_name(), id);
+                self.get_record::<T>(id, &kind).await.map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Refuses branch ids that do not exist. 0 means no branch.
+    async fn require_branch(&self, branch_id: u16) -> Result<(), String> {
+        if branch_id == 0 || self.branches.read().await.contains_key(&branch_id) {
+            Ok(())
+        } else {
+            Err(format!("Branch {} not found", branch_id))
+        }
+    }
+
+    /// Deletes an employee that nobody reports to and that ranks below the current user.
+    /// Nobody can delete their own account.
+    async fn delete_employee(&self, employee_id: u16) -> Result<(), String> {
+        if self.get_current_user().await?.get_id() == employee_id {
+            return Err("You cannot delete your own account".to_string());
+        }
+        self.require_change_over(employee_id).await?;
+        if !utils::org::direct_reports(&*self.employees.read().await, employee_id).is_empty() {
+            return Err("Employees still report to this employee".to_string());
+        }
+        self.remove_obj::<Employee>(employee_id).await
+    }
+
+    /// Deletes a branch that holds no media and no employees.
+    async fn delete_branch(&self, branch_id: u16) -> Result<(), String> {
+        if self.get_media().await.values().any(|m| m.branch_id() == branch_id) {
+            return Err("Media are still held at this branch".to_string());
+        }
+        if !self.find_employees(|emp| emp.branch_id() == branch_id).await?.is_empty() {
+            return Err("Employees still work at this branch".to_string());
+        }
+        self.remove_obj::<Branch>(branch_id).await
+    }
+
+    /// Deletes a vendor that no media point at.
+    async fn delete_vendor(&self, vendor_id: u16) -> Result<(), String> {
+        if self.get_media().await.values().any(|m| m.vendor_id() == Some(vendor_id)) {
+            return Err("Media still come from this vendor".to_string());
+        }
+        self.remove_obj::<Vendor>(vendor_id).await
+    }
+
+    pub async fn get_media(&self) -> RwLockReadGuard<'_, HashMap<u16, Media>> {
+        self.media.read().await
+    }
+}
+
+/// A table that the generic record methods can reach, see `Table`. Tables with rules of
+/// their own, such as names that other rows copy, override `save` and `delete`.
+pub trait Record: DisplayInfo + Default + Clone + Send + Sync + 'static {
+    /// The in-memory copy of the table, for the tables that `App` caches.
+    fn cache(_app: &App) -> Option<&RwLock<HashMap<u16, Self>>> {
+        None
+    }
+
+    /// What the generic record methods hand out.
+    fn view(&self) -> Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+
+    /// The event published when a cached record is written, or removed for `None`.
+    fn changed(_id: u16, _record: Option<&Self>) -> Option<AppEvent> {
+        None
+    }
+
+    /// Writes a record that has been checked already, inserting it when `created` is set.
+    fn save(
+        app: &App,
+        record: Self,
+        created: bool,
+    ) -> impl Future<Output = Result<(), String>> + Send {
+        app.write_obj(record, created)
+    }
+
+    fn delete(app: &App, id: u16) -> impl Future<Output = Result<(), String>> + Send {
+        app.remove_obj::<Self>(id)
+    }
+
+    /// Checks rows before `App::import_table` writes them as they are.
+    fn check_import(
+        _app: &App,
+        _records: &[Self],
+    ) -> impl Future<Output = Result<(), String>> + Send {
+        async { Ok(()) }
+    }
+}
+
+impl Record for Employee {
+    fn cache(app: &App) -> Option<&RwLock<HashMap<u16, Self>>> {
+        Some(&app.employees)
+    }
+
+    fn view(&self) -> Value {
+        serde_json::to_value(EmployeeInfo::from(self)).unwrap_or_default()
+    }
+
+    fn save(
+        app: &App,
+        record: Self,
+        created: bool,
+    ) -> impl Future<Output = Result<(), String>> + Send {
+        app.save_employee_record(record, created)
+    }
+
+    fn changed(id: u16, record: Option<&Self>) -> Option<AppEvent> {
+        Some(match record {
+            Some(_) => AppEvent::EmployeeUpdated { id },
+            None => AppEvent::EmployeeRemoved { id },
+        })
+    }
+
+    fn delete(app: &App, id: u16) -> impl Future<Output = Result<(), String>> + Send {
+        app.delete_employee(id)
+    }
+
+    fn check_import(
+        app: &App,
+        records: &[Self],
+    ) -> impl Future<Output = Result<(), String>> + Send {
+        app.check_employee_import(records)
+    }
+}
+
+impl Record for Media {
+    fn cache(app: &App) -> Option<&RwLock<HashMap<u16, Self>>> {
+        Some(&app.media)
+    }
+
+    fn save(
+        app: &App,
+        record: Self,
+        created: bool,
+    ) -> impl Future<Output = Result<(), String>> + Send {
+        app.save_media_record(record, created)
+    }
+
+    fn changed(id: u16, record: Option<&Self>) -> Option<AppEvent> {
+        Some(match record {
+            Some(media) => AppEvent::MediaChanged { media: media.clone() },
+            None => AppEvent::MediaRemoved { id },
+        })
+    }
+}
+
+impl Record for Branch {
+    fn cache(app: &App) -> Option<&RwLock<HashMap<u16, Self>>> {
+        Some(&app.branches)
+    }
+
+    fn save(
+        app: &App,
+        record: Self,
+        _created: bool,
+    ) -> impl Future<Output = Result<(), String>> + Send {
+        app.save_branch(record)
+    }
+
+    fn delete(app: &App, id: u16) -> impl Future<Output = Result<(), String>> + Send {
+        app.delete_branch(id)
+    }
+}
+
+impl Record for PartnerLibrary {
+    fn save(
+        app: &App,
+        record: Self,
+        _created: bool,
+    ) -> impl Future<Output = Result<(), String>> + Send {
+        app.save_partner_library(record)
+    }
+}
+
+impl Record for Vendor {
+    fn save(
+        app: &App,
+        record: Self,
+        _created: bool,
+    ) -> impl Future<Output = Result<(), String>> + Send {
+        app.save_vendor(record)
+    }
+
+    fn delete(app: &App, id: u16) -> impl Future<Output = Result<(), String>> + Send {
+        app.delete_vendor(id)
+    }
+}
+
+impl Record for Department {
+    fn save(
+        app: &App,
+        record: Self,
+        _created: bool,
+    ) -> impl Future<Output = Result<(), String>> + Send {
+        app.save_department(record)
+    }
+
+    fn delete(app: &App, id: u16) -> impl Future<Output = Result<(), String>> + Send {
+        app.delete_department(id)
+    }
+}
+
+impl Record for Project {
+    fn save(
+        app: &App,
+        record: Self,
+        _created: bool,
+    ) -> impl Future<Output = Result<(), String>> + Send {
+        app.save_project(record)
+    }
+
+    fn delete(app: &App, id: u16) -> impl Future<Output = Result<(), String>> + Send {
+        app.delete_project(id)
+    }
+}
+
+/// The rows of a table that older databases may not have yet. A missing table is logged
+/// and treated as empty, see `migrations/` for the statements that add it.
+fn optional_table<T: DisplayInfo>(rows: Result<Vec<T>, DbToolError>) -> Vec<T> {
+    rows.unwrap_or_else(|e| {
+        eprintln!("Skipping the {} table: {}", T::get_table_name(), e);
+        Vec::new()
+    })
+}
+
+/// Applies payload fields to a copy of `record` as the table's schema allows. Unknown
+/// fields and changes to read-only ones are refused, and every field has to keep to its rules.
+fn merge_fields<T: DisplayInfo>(record: &T, fields: &Map<String, Value>) -> Result<T, String> {
+    let schema = T::schema();
+    let mut json_obj = serde_json::to_value(record).map_err(|e| e.to_string())?;
+    for (field_name, new_value) in fields {
+        let field = schema
+            .iter()
+            .find(|field| field.name == field_name)
+            .ok_or_else(|| format!("Unknown field {}", field_name))?;
+        if field.read_only && json_obj.get(field_name) != Some(new_value) {
+            return Err(format!("{} is read-only", field_name));
+        }
+        json_obj[field_name] = new_value.clone();
+    }
+    for field in &schema {
+        field.check(&json_obj[field.name])?;
+    }
+    serde_json::from_value(json_obj).map_err(|e| format!("Invalid {}: {}", T::get_table_name(), e))
+}
+
+/// Reads (field, value) pairs from the command line as the table's schema says.
+fn parse_fields<T: DisplayInfo>(values: &[(String, String)]) -> Result<Map<String, Value>, String> {
+    let schema = T::schema();
+    let mut fields = Map::new();
+    for (field_name, input) in values {
+        let field = schema
+            .iter()
+            .find(|field| field.name == field_name)
+            .ok_or_else(|| format!("Unknown field {}", field_name))?;
+        let value = field
+            .parse(input)
+            .map_err(|e| format!("{} for {}", e, field_name))?;
+        fields.insert(field_name.clone(), value);
+    }
+    Ok(fields)
+}
+
+/// Asks for a field on stdin, listing the choices of enum fields.
+fn prompt_for(field: &FieldSchema) -> String {
+    match &field.kind {
+        FieldKind::Choice { choices } => {
+            let labels: Vec<&str> = choices.iter().map(|choice| choice.label.as_str()).collect();
+            println!("Enter a value for {} ({})", field.name, labels.join(", "));
+        }
+        _ => println!("Enter a value for {}", field.name),
     }
+    utils::user::get_input()
 }
@@ -0,0 +1,408 @@
+//! `rlm-admin` maintains employees and media from the command line. Every command runs as an
+//! Admin account and goes through the same checks as the app.
+//!
+//! Missing values are prompted for, unless `--no-input` is given, which makes it usable from
+//! scripts. Passwords can be passed through `RLM_ADMIN_PASSWORD` to keep them out of the
+//! process list.
+
+use rusty_library_management::app::Record;
+use rusty_library_management::config::ConfigLocations;
+use rusty_library_management::types::enums::TotpStatus;
+use rusty_library_management::types::structs::{DisplayInfo, Employee, Media};
+use rusty_library_management::{utils, App, Config};
+use std::fmt::Display;
+use std::path::PathBuf;
+
+const USAGE: &str = "\
+Usage: rlm-admin [options] <command>
+
+Commands:
+    employee|media list                 List ids and names
+    employee|media show <id>            Print one record
+    employee|media create               Create a record from --set values and prompts
+    employee|media update [<id>]        Change a record, --set values or a prompt
+    employee|media delete [<id>]        Delete a record
+    refresh                             Reload all data and print a summary
+    export employee|media               Write every row as JSON, to stdout or --output
+    import employee|media <file>        Insert or overwrite the rows of an exported file
+
+Options:
+    --config <path>         Config file to use, also RLM_CONFIG
+    --user <id>             Admin account to run as, also RLM_ADMIN_USER
+    --password <password>   Its password, also RLM_ADMIN_PASSWORD
+    --code <code>           Two-factor code, if the account needs one
+    --set <field>=<value>   A field value for create and update, can be repeated
+    --output <path>         File to export to
+    --yes                   Do not ask before deleting
+    --no-input              Never prompt, fail on missing values instead
+    --help                  Show this message
+
+Exported employees include password hashes, keep those files private.";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Table {
+    Employee,
+    Media,
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    List(Table),
+    Show(Table, u16),
+    Create(Table),
+    Update(Table, Option<u16>),
+    Delete(Table, Option<u16>),
+    Refresh,
+    Export(Table),
+    Import(Table, PathBuf),
+    Help,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Args {
+    user: Option<u16>,
+    password: Option<String>,
+    code: Option<String>,
+    values: Vec<(String, String)>,
+    output: Option<PathBuf>,
+    yes: bool,
+    no_input: bool,
+    command: Vec<String>,
+}
+
+impl Args {
+    /// Splits flags from the command words. `--config` is read by `ConfigLocations`.
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+        let mut args = args.into_iter();
+        let mut parsed = Args::default();
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value)),
+                _ => (arg.clone(), None),
+            };
+            let mut value = || {
+                inline
+                    .map(str::to_string)
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("{} needs a value", flag))
+            };
+            match flag.as_str() {
+                "--config" => {
+                    value()?;
+                }
+                "--user" => {
+                    let id = value()?;
+                    parsed.user = Some(id.parse().map_err(|_| format!("Invalid user id {}", id))?);
+                }
+                "--password" => parsed.password = Some(value()?),
+                "--code" => parsed.code = Some(value()?),
+                "--set" => {
+                    let pair = value()?;
+                    let (field, value) = pair
+                        .split_once('=')
+                        .ok_or_else(|| format!("Expected <field>=<value>, got {}", pair))?;
+                    parsed.values.push((field.to_string(), value.to_string()));
+                }
+                "--output" => parsed.output = Some(PathBuf::from(value()?)),
+                "--yes" => parsed.yes = true,
+                "--no-input" => parsed.no_input = true,
+                "--help" | "-h" => parsed.command = vec!["help".to_string()],
+                _ if flag.starts_with("--") => return Err(format!("Unknown option {}", flag)),
+                _ => parsed.command.push(arg),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn command(&self) -> Result<Command, String> {
+        let words: Vec<&str> = self.command.iter().map(String::as_str).collect();
+        let command = match words.as_slice() {
+            [] | ["help"] => Command::Help,
+            ["refresh"] => Command::Refresh,
+            ["export", table] => Command::Export(parse_table(table)?),
+            ["import", table, file] => Command::Import(parse_table(table)?, PathBuf::from(file)),
+            [table, "list"] => Command::List(parse_table(table)?),
+            [table, "show", id] => Command::Show(parse_table(table)?, parse_id(id)?),
+            [table, "create"] => Command::Create(parse_table(table)?),
+            [table, "update"] => Command::Update(parse_table(table)?, None),
+            [table, "update", id] => Command::Update(parse_table(table)?, Some(parse_id(id)?)),
+            [table, "delete"] => Command::Delete(parse_table(table)?, None),
+            [table, "delete", id] => Command::Delete(parse_table(table)?, Some(parse_id(id)?)),
+            _ => return Err(format!("Unknown command '{}'", words.join(" "))),
+        };
+        match command {
+            Command::Create(_) | Command::Update(..) => {}
+            _ if !self.values.is_empty() => {
+                return Err("--set only applies to create and update".to_string())
+            }
+            _ => {}
+        }
+        Ok(command)
+    }
+}
+
+fn parse_table(name: &str) -> Result<Table, String> {
+    match name.to_lowercase().as_str() {
+        "employee" | "employees" => Ok(Table::Employee),
+        "media" => Ok(Table::Media),
+        _ => Err(format!(
+            "Unknown table {}, expected employee or media",
+            name
+        )),
+    }
+}
+
+fn parse_id(id: &str) -> Result<u16, String> {
+    id.parse().map_err(|_| format!("Invalid id {}", id))
+}
+
+/// Takes a value from a flag, then the environment, then a prompt.
+fn resolve(
+    flag: Option<String>,
+    env: &str,
+    prompt: &str,
+    no_input: bool,
+) -> Result<String, String> {
+    if let Some(value) = flag.or_else(|| std::env::var(env).ok()) {
+        return Ok(value);
+    }
+    if no_input {
+        return Err(format!("{} is required with --no-input", prompt));
+    }
+    println!("{}:", prompt);
+    Ok(utils::user::get_input())
+}
+
+async fn login(app: &App, args: &Args) -> Result<(), String> {
+    let user = resolve(
+        args.user.map(|id| id.to_string()),
+        "RLM_ADMIN_USER",
+        "Employee id",
+        args.no_input,
+    )?;
+    let user = parse_id(&user)?;
+    let password = resolve(
+        args.password.clone(),
+        "RLM_ADMIN_PASSWORD",
+        "Password",
+        args.no_input,
+    )?;
+    if !app.authenticate_employee(user, &password).await? {
+        return Err("Invalid id or password".to_string());
+    }
+    match app.totp_status().await {
+        TotpStatus::Verified => {}
+        TotpStatus::CodeRequired => {
+            let code = resolve(
+                args.code.clone(),
+                "RLM_ADMIN_CODE",
+                "Two-factor code",
+                args.no_input,
+            )?;
+            if !app.verify_totp(&code).await? {
+                return Err("Invalid two-factor code".to_string());
+            }
+        }
+        TotpStatus::EnrolmentRequired => {
+            return Err("Set up two-factor authentication in the app first".to_string())
+        }
+    }
+    if app.password_change_required().await {
+        return Err("Change the password of this account in the app first".to_string());
+    }
+    Ok(())
+}
+
+async fn run_table<T>(app: &App, args: &Args, command: Command) -> Result<(), String>
+where
+    T: Record + Display,
+{
+    match command {
+        Command::List(_) => {
+            let mut items = app.export_table::<T>().await?;
+            items.sort_by_key(|item| item.get_id());
+            items
+                .iter()
+                .for_each(|item| println!("{} -> {}", item.get_id(), item.get_name()));
+        }
+        Command::Show(_, id) => {
+            let item = app
+                .export_table::<T>()
+                .await?
+                .into_iter()
+                .find(|item| item.get_id() == id)
+                .ok_or_else(|| format!("{} not found", T::get_table_name()))?;
+            println!("{}", item);
+        }
+        Command::Create(_) => {
+            let item = app.create_obj::<T>(&args.values, !args.no_input).await?;
+            println!("Created {} {}", T::get_table_name(), item.get_id());
+            if T::get_table_name() == Employee::get_table_name() {
+                // New accounts have no password, the reset code lets them set one
+                let code = app.issue_reset_code(item.get_id()).await?;
+                println!("One-time reset code: {}", code);
+            }
+        }
+        Command::Update(_, id) => {
+            if args.no_input && args.values.is_empty() {
+                return Err("Nothing to change, pass --set <field>=<value>".to_string());
+            }
+            let id = select::<T>(app, args, id).await?;
+            app.update_obj::<T>(id, &args.values).await?;
+            println!("Updated {} {}", T::get_table_name(), id);
+        }
+        Command::Delete(_, id) => {
+            let id = select::<T>(app, args, id).await?;
+            if !args.yes {
+                if args.no_input {
+                    return Err("Pass --yes to delete without a prompt".to_string());
+                }
+                println!("Delete {} {}? [y/N]", T::get_table_name(), id);
+                if !utils::user::get_input().eq_ignore_ascii_case("y") {
+                    return Err("Cancelled".to_string());
+                }
+            }
+            app.delete_obj::<T>(id).await?;
+            println!("Deleted {} {}", T::get_table_name(), id);
+        }
+        Command::Export(_) => {
+            let items = app.export_table::<T>().await?;
+            let json = serde_json::to_string_pretty(&items).map_err(|e| e.to_string())?;
+            match &args.output {
+                Some(path) => {
+                    std::fs::write(path, json)
+                        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+                    eprintln!("Exported {} rows to {}", items.len(), path.display());
+                }
+                None => println!("{}", json),
+            }
+        }
+        Command::Import(_, path) => {
+            let contents = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            let items: Vec<T> = serde_json::from_str(&contents)
+                .map_err(|e| format!("Invalid {} file: {}", T::get_table_name(), e))?;
+            let count = app.import_table(&items).await?;
+            println!("Imported {} rows into {}", count, T::get_table_name());
+        }
+        Command::Refresh | Command::Help => unreachable!(),
+    }
+    Ok(())
+}
+
+/// Uses the id from the command line, or lets the user pick one.
+async fn select<T>(app: &App, args: &Args, id: Option<u16>) -> Result<u16, String>
+where
+    T: Record + Display,
+{
+    if let Some(id) = id {
+        return Ok(id);
+    }
+    if args.no_input {
+        return Err("An id is required with --no-input".to_string());
+    }
+    app.select_obj::<T>()
+        .await?
+        .map(|item| item.get_id())
+        .ok_or_else(|| "No such item".to_string())
+}
+
+async fn run(args: Args) -> Result<(), String> {
+    let command = args.command()?;
+    if command == Command::Help {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+
+    let path = ConfigLocations::for_tools()
+        .discover()
+        .map_err(|e| e.to_string())?;
+    utils::loading::set_config_file(path);
+    let config = Config::load().map_err(|e| e.to_string())?;
+    let app = App::new(config).await?;
+    app.run().await?;
+    login(&app, &args).await?;
+
+    let table = match command {
+        Command::Refresh => {
+            app.refresh_all_data().await?;
+            println!(
+                "Loaded {} employees and {} media",
+                app.get_employees().await?.len(),
+                app.get_media().await.len()
+            );
+            return Ok(());
+        }
+        Command::List(table)
+        | Command::Show(table, _)
+        | Command::Create(table)
+        | Command::Update(table, _)
+        | Command::Delete(table, _)
+        | Command::Export(table)
+        | Command::Import(table, _) => table,
+        Command::Help => unreachable!(),
+    };
+    match table {
+        Table::Employee => run_table::<Employee>(&app, &args, command).await,
+        Table::Media => run_table::<Media>(&app, &args, command).await,
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let result = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => run(args).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+// ---------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Result<Args, String> {
+        Args::parse(line.split_whitespace().map(str::to_string))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let parsed =
+            args("--config x.ini --user=3 --no-input employee update 7 --set name=Ann").unwrap();
+        assert_eq!(parsed.user, Some(3));
+        assert!(parsed.no_input);
+        assert_eq!(parsed.values, vec![("name".to_string(), "Ann".to_string())]);
+        assert_eq!(
+            parsed.command().unwrap(),
+            Command::Update(Table::Employee, Some(7))
+        );
+
+        assert_eq!(
+            args("media delete").unwrap().command().unwrap(),
+            Command::Delete(Table::Media, None)
+        );
+        assert_eq!(
+            args("export Employees --output out.json")
+                .unwrap()
+                .command()
+                .unwrap(),
+            Command::Export(Table::Employee)
+        );
+        assert_eq!(args("").unwrap().command().unwrap(), Command::Help);
+
+        assert!(args("--user").is_err());
+        assert!(args("--user abc").is_err());
+        assert!(args("--set name").is_err());
+        assert!(args("--verbose").is_err());
+        assert!(args("books list").unwrap().command().is_err());
+        assert!(args("media show x").unwrap().command().is_err());
+        assert!(args("media list --set a=b").unwrap().command().is_err());
+    }
+}
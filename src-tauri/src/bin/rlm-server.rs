@@ -0,0 +1,26 @@
+//! `rlm-server` serves the library over a local HTTP/JSON API, for self-checkout kiosks and
+//! the intranet site. See `rusty_library_management::server` for the endpoints.
+
+use rusty_library_management::config::ConfigLocations;
+use rusty_library_management::{server, utils, App, Config};
+
+async fn run() -> Result<(), String> {
+    let path = ConfigLocations::for_tools()
+        .discover()
+        .map_err(|e| e.to_string())?;
+    eprintln!("Using config file {}", path.display());
+    utils::loading::set_config_file(path);
+    let config = Config::load().map_err(|e| e.to_string())?;
+    let server_config = config.server.clone();
+    let app = App::new(config).await?;
+    app.run().await?;
+    server::run(app, server_config).await
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(e) = run().await {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,635 @@
+use crate::types::enums::PermissionLevel;
+use crate::types::structs::LockoutPolicy;
+use crate::utils::security::HashParams;
+use crate::utils::loading;
+use configparser::ini::Ini;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use ts_rs::TS;
+
+pub const CONFIG_FILE_NAME: &str = "config.ini";
+
+/// The profile made up of the `[DBSettings]` section itself.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Prefix of the sections that define additional database profiles, e.g. `[profile.training]`.
+const PROFILE_PREFIX: &str = "profile.";
+
+/// Written on first run when no config file can be found.
+pub const TEMPLATE: &str = include_str!("../resources/config.ini");
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
+    Io(String),
+    Parse(String),
+    Missing {
+        section: String,
+        key: &'static str,
+    },
+    Invalid {
+        section: String,
+        key: &'static str,
+        value: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "Failed to read config file: {}", e),
+            ConfigError::Parse(e) => write!(f, "Config file is corrupt: {}", e),
+            ConfigError::Missing { section, key } => {
+                write!(f, "Missing setting [{}] {}", section, key)
+            }
+            ConfigError::Invalid {
+                section,
+                key,
+                value,
+                reason,
+            } => write!(f, "Invalid value '{}' for [{}] {}: {}", value, section, key, reason),
+        }
+    }
+}
+
+impl ConfigError {
+    /// True when the file has no database endpoint yet, as on a fresh install.
+    pub fn is_unconfigured(&self) -> bool {
+        matches!(self, ConfigError::Missing { section, key: "endpoint" } if *section == DATABASE.name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The connection settings of the active profile
+    pub database: DatabaseConfig,
+    pub active_profile: String,
+    /// Every configured profile by name, including `default`
+    pub profiles: BTreeMap<String, DatabaseConfig>,
+    pub security: SecurityConfig,
+    pub loans: LoanConfig,
+    pub ui: UiConfig,
+    pub server: ServerConfig,
+}
+
+#[derive(Debug, Clone)]
+pub struct DatabaseConfig {
+    pub endpoint: String,
+    /// May still be encrypted, see `utils::loading::reveal_setting`
+    pub api_key: String,
+    pub salt: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct SecurityConfig {
+    pub lockout: LockoutPolicy,
+    pub hashing: HashParams,
+    pub password_history: usize,
+    /// May still be encrypted, see `utils::loading::reveal_setting`
+    pub totp_key: String,
+    pub totp_required_level: PermissionLevel,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoanConfig {
+    pub loan_days: u16,
+    pub max_loans: u16,
+}
+
+#[derive(Serialize, TS, Debug, Clone)]
+#[ts(export_to = "../src/bindings/")]
+pub struct UiConfig {
+    pub theme: String,
+    pub page_size: u16,
+}
+
+/// Settings of the HTTP API started by `rlm-server`.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub address: SocketAddr,
+    /// How long a token stays valid after it was last used
+    pub token_minutes: u16,
+    /// Origin allowed to call the API from a browser, e.g. the intranet site. Empty disables
+    /// cross-origin requests.
+    pub allowed_origin: String,
+}
+
+/// The profile names shown to Devs, without any connection details.
+#[derive(Serialize, TS, Debug, Clone)]
+#[ts(export_to = "../src/bindings/")]
+pub struct ProfileList {
+    pub profiles: Vec<String>,
+    pub active: String,
+}
+
+/// An INI section and the name it goes by in `RLM_<SECTION>_<KEY>` environment overrides.
+struct Section {
+    name: Cow<'static, str>,
+    env: Cow<'static, str>,
+}
+
+impl Section {
+    /// The section of a named profile, overridden with `RLM_PROFILE_<NAME>_<KEY>`.
+    fn profile(name: &str) -> Section {
+        Section {
+            name: Cow::Owned(format!("{}{}", PROFILE_PREFIX, name)),
+            env: Cow::Owned(format!("PROFILE_{}", name.to_uppercase().replace('-', "_"))),
+        }
+    }
+}
+
+const DATABASE: Section = Section {
+    name: Cow::Borrowed("DBSettings"),
+    env: Cow::Borrowed("DATABASE"),
+};
+const SECURITY: Section = Section {
+    name: Cow::Borrowed("Security"),
+    env: Cow::Borrowed("SECURITY"),
+};
+const LOANS: Section = Section {
+    name: Cow::Borrowed("Loans"),
+    env: Cow::Borrowed("LOANS"),
+};
+const UI: Section = Section {
+    name: Cow::Borrowed("UI"),
+    env: Cow::Borrowed("UI"),
+};
+const SERVER: Section = Section {
+    name: Cow::Borrowed("Server"),
+    env: Cow::Borrowed("SERVER"),
+};
+
+struct Sources<'a> {
+    ini: Ini,
+    env: &'a dyn Fn(&str) -> Option<String>,
+}
+
+impl Sources<'_> {
+    fn raw(&self, section: &Section, key: &str) -> Option<String> {
+        (self.env)(&format!("RLM_{}_{}", section.env, key.to_uppercase()))
+            .or_else(|| self.ini.get(&section.name, key))
+    }
+
+    fn string(&self, section: &Section, key: &'static str, default: &str) -> String {
+        self.raw(section, key).unwrap_or_else(|| default.to_string())
+    }
+
+    fn required(&self, section: &Section, key: &'static str) -> Result<String, ConfigError> {
+        self.raw(section, key)
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| ConfigError::Missing {
+                section: section.name.to_string(),
+                key,
+            })
+    }
+
+    /// Parses a setting, falling back to `default` when it is missing or left blank.
+    fn parse<T>(
+        &self,
+        section: &Section,
+        key: &'static str,
+        default: T,
+        parse: impl Fn(&str) -> Result<T, String>,
+    ) -> Result<T, ConfigError> {
+        match self.raw(section, key).filter(|value| !value.is_empty()) {
+            None => Ok(default),
+            Some(value) => parse(&value).map_err(|reason| ConfigError::Invalid {
+                section: section.name.to_string(),
+                key,
+                value,
+                reason,
+            }),
+        }
+    }
+
+    fn database(&self, section: &Section) -> Result<DatabaseConfig, ConfigError> {
+        Ok(DatabaseConfig {
+            endpoint: self.required(section, "endpoint")?,
+            api_key: self.required(section, "api_key")?,
+            salt: self.string(section, "salt", ""),
+        })
+    }
+
+    /// Reads `[DBSettings]` as the default profile and every `[profile.<name>]` section.
+    /// Section names are case insensitive, so profile names are always lower case.
+    fn profiles(&self) -> Result<BTreeMap<String, DatabaseConfig>, ConfigError> {
+        let mut profiles = BTreeMap::new();
+        profiles.insert(DEFAULT_PROFILE.to_string(), self.database(&DATABASE)?);
+        for section in self.ini.sections() {
+            if let Some(name) = section.strip_prefix(PROFILE_PREFIX) {
+                if name.is_empty() || name == DEFAULT_PROFILE {
+                    return Err(ConfigError::Parse(format!(
+                        "[{}] is not a valid profile name",
+                        section
+                    )));
+                }
+                profiles.insert(name.to_string(), self.database(&Section::profile(name))?);
+            }
+        }
+        Ok(profiles)
+    }
+
+    fn number<T: std::str::FromStr>(
+        &self,
+        section: &Section,
+        key: &'static str,
+        default: T,
+    ) -> Result<T, ConfigError> {
+        self.parse(section, key, default, |value| {
+            value
+                .parse()
+                .map_err(|_| "expected a whole number".to_string())
+        })
+    }
+}
+
+fn invalid(section: &Section, key: &'static str, value: impl ToString, reason: &str) -> ConfigError {
+    ConfigError::Invalid {
+        section: section.name.to_string(),
+        key,
+        value: value.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+/// The places a config file is looked for, from highest to lowest priority.
+#[derive(Debug, Default)]
+pub struct ConfigLocations {
+    /// Given with `--config <path>` on the command line
+    pub cli: Option<PathBuf>,
+    /// Given with the `RLM_CONFIG` environment variable
+    pub env: Option<PathBuf>,
+    /// The platform specific app config directory. On first run the bundled config file, or
+    /// a template, is copied here, since the bundle may be read-only
+    pub app_config_dir: Option<PathBuf>,
+    /// Config files bundled next to the executable. They are only used in place when there
+    /// is no app config directory, as for the command line tools
+    pub resources: Vec<PathBuf>,
+}
+
+impl ConfigLocations {
+    /// Reads the command line flag and environment variable for this process.
+    pub fn from_process(app_config_dir: Option<PathBuf>, resources: Vec<PathBuf>) -> Self {
+        let mut args = std::env::args().skip(1);
+        let mut cli = None;
+        while let Some(arg) = args.next() {
+            if arg == "--config" {
+                cli = args.next().map(PathBuf::from);
+            } else if let Some(path) = arg.strip_prefix("--config=") {
+                cli = Some(PathBuf::from(path));
+            }
+        }
+        ConfigLocations {
+            cli,
+            env: std::env::var_os("RLM_CONFIG").map(PathBuf::from),
+            app_config_dir,
+            resources,
+        }
+    }
+
+    /// The locations used by the command line tools, which have no app config directory.
+    pub fn for_tools() -> Self {
+        ConfigLocations::from_process(
+            None,
+            vec![PathBuf::from("./resources").join(CONFIG_FILE_NAME)],
+        )
+    }
+
+    /// Returns the config file to use. Explicitly requested files have to exist. Otherwise
+    /// the file in the app config directory is used, created from the bundled one on first
+    /// run, so settings and the machine key are always written somewhere writable.
+    pub fn discover(&self) -> Result<PathBuf, ConfigError> {
+        if let Some(path) = self.cli.as_ref().or(self.env.as_ref()) {
+            if !path.is_file() {
+                return Err(ConfigError::Io(format!("{} does not exist", path.display())));
+            }
+            return Ok(path.clone());
+        }
+
+        let bundled = self.resources.iter().find(|path| path.is_file());
+        let Some(path) = self
+            .app_config_dir
+            .as_ref()
+            .map(|dir| dir.join(CONFIG_FILE_NAME))
+        else {
+            return bundled.cloned().ok_or_else(|| {
+                ConfigError::Io("No config file found and no app config directory".to_string())
+            });
+        };
+        if path.is_file() {
+            return Ok(path);
+        }
+
+        let contents = match bundled {
+            Some(bundled) => std::fs::read_to_string(bundled)
+                .map_err(|e| ConfigError::Io(format!("{}: {}", bundled.display(), e)))?,
+            None => TEMPLATE.to_string(),
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| ConfigError::Io(e.to_string()))?;
+        }
+        std::fs::write(&path, contents).map_err(|e| ConfigError::Io(e.to_string()))?;
+        println!("Generated a new config file at {}", path.display());
+        Ok(path)
+    }
+}
+
+impl Config {
+    /// Loads the config file in use, applying `RLM_*` environment overrides.
+    pub fn load() -> Result<Config, ConfigError> {
+        Config::load_from(loading::config_file())
+    }
+
+    pub fn load_from(path: &Path) -> Result<Config, ConfigError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError::Io(format!("{}: {}", path.display(), e)))?;
+        Config::parse(&contents, &|name| std::env::var(name).ok())
+    }
+
+    pub fn parse(contents: &str, env: &dyn Fn(&str) -> Option<String>) -> Result<Config, ConfigError> {
+        let mut ini = Ini::new();
+        ini.read(contents.to_string()).map_err(ConfigError::Parse)?;
+        let src = Sources { ini, env };
+
+        let profiles = src.profiles()?;
+        let active_profile = src
+            .string(&DATABASE, "profile", DEFAULT_PROFILE)
+            .to_lowercase();
+        let database = profiles.get(&active_profile).cloned().ok_or_else(|| {
+            invalid(&DATABASE, "profile", &active_profile, "no such profile is configured")
+        })?;
+
+        let lockout_defaults = LockoutPolicy::default();
+        let hash_defaults = HashParams::default();
+        let config = Config {
+            database,
+            active_profile,
+            profiles,
+            security: SecurityConfig {
+                lockout: LockoutPolicy {
+                    max_attempts: src.number(
+                        &SECURITY,
+                        "max_login_attempts",
+                        lockout_defaults.max_attempts,
+                    )?,
+                    lockout_secs: src.number(
+                        &SECURITY,
+                        "lockout_seconds",
+                        lockout_defaults.lockout_secs,
+                    )?,
+                    base_delay_secs: src.number(
+                        &SECURITY,
+                        "login_delay_seconds",
+                        lockout_defaults.base_delay_secs,
+                    )?,
+                },
+                hashing: HashParams {
+                    variant: src.parse(&SECURITY, "hash_variant", hash_defaults.variant, |value| {
+                        argon2::Variant::from_str(value)
+                            .map_err(|_| "expected argon2i, argon2d or argon2id".to_string())
+                    })?,
+                    mem_cost: src.number(&SECURITY, "hash_mem_cost", hash_defaults.mem_cost)?,
+                    time_cost: src.number(&SECURITY, "hash_time_cost", hash_defaults.time_cost)?,
+                    lanes: src.number(&SECURITY, "hash_lanes", hash_defaults.lanes)?,
+                },
+                password_history: src.number(&SECURITY, "password_history", 5)?,
+                totp_key: src.string(&SECURITY, "totp_key", ""),
+                totp_required_level: src.parse(
+                    &SECURITY,
+                    "totp_required_level",
+                    PermissionLevel::None,
+                    |value| value.parse(),
+                )?,
+            },
+            loans: LoanConfig {
+                loan_days: src.number(&LOANS, "loan_days", 14)?,
+                max_loans: src.number(&LOANS, "max_loans", 5)?,
+            },
+            ui: UiConfig {
+                theme: src.string(&UI, "theme", "light"),
+                page_size: src.number(&UI, "page_size", 25)?,
+            },
+            server: ServerConfig {
+                address: src.parse(
+                    &SERVER,
+                    "address",
+                    SocketAddr::from(([127, 0, 0, 1], 8080)),
+                    |value| {
+                        value
+                            .parse()
+                            .map_err(|_| "expected an address like 127.0.0.1:8080".to_string())
+                    },
+                )?,
+                token_minutes: src.number(&SERVER, "token_minutes", 60)?,
+                allowed_origin: src.string(&SERVER, "allowed_origin", ""),
+            },
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Returns the connection settings of a configured profile.
+    pub fn profile(&self, name: &str) -> Result<&DatabaseConfig, String> {
+        self.profiles
+            .get(&name.to_lowercase())
+            .ok_or_else(|| format!("No profile named '{}' is configured", name))
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        for (name, profile) in &self.profiles {
+            let endpoint = &profile.endpoint;
+            if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") {
+                let section = match name.as_str() {
+                    DEFAULT_PROFILE => DATABASE,
+                    _ => Section::profile(name),
+                };
+                return Err(invalid(&section, "endpoint", endpoint, "expected an http(s) URL"));
+            }
+        }
+
+        let security = &self.security;
+        if security.lockout.max_attempts == 0 {
+            return Err(invalid(&SECURITY, "max_login_attempts", 0, "must be at least 1"));
+        }
+        if security.hashing.lanes == 0 {
+            return Err(invalid(&SECURITY, "hash_lanes", 0, "must be at least 1"));
+        }
+        if security.hashing.time_cost == 0 {
+            return Err(invalid(&SECURITY, "hash_time_cost", 0, "must be at least 1"));
+        }
+        // Nobody could enrol, which would lock every account at the level out
+        if security.totp_required_level != PermissionLevel::None && security.totp_key.is_empty() {
+            return Err(invalid(
+                &SECURITY,
+                "totp_required_level",
+                &security.totp_required_level,
+                "needs totp_key to be set, or use None",
+            ));
+        }
+        if security.hashing.mem_cost < 8 * security.hashing.lanes {
+            return Err(invalid(
+                &SECURITY,
+                "hash_mem_cost",
+                security.hashing.mem_cost,
+                "must be at least 8 times hash_lanes",
+            ));
+        }
+
+        if self.loans.loan_days == 0 {
+            return Err(invalid(&LOANS, "loan_days", 0, "must be at least 1"));
+        }
+
+        if !["light", "dark"].contains(&self.ui.theme.as_str()) {
+            return Err(invalid(&UI, "theme", &self.ui.theme, "expected light or dark"));
+        }
+        if !(1..=500).contains(&self.ui.page_size) {
+            return Err(invalid(&UI, "page_size", self.ui.page_size, "expected 1 to 500"));
+        }
+
+        if self.server.token_minutes == 0 {
+            return Err(invalid(&SERVER, "token_minutes", 0, "must be at least 1"));
+        }
+        Ok(())
+    }
+}
+
+// Tests
+// ---------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINIMAL: &str = "[DBSettings]\nendpoint = https://example.com\napi_key = key\n";
+
+    fn no_env(_: &str) -> Option<String> {
+        None
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = Config::parse(MINIMAL, &no_env).unwrap();
+        assert_eq!(config.database.salt, "");
+        assert_eq!(config.security.lockout.max_attempts, 5);
+        assert_eq!(config.security.totp_required_level, PermissionLevel::None);
+        assert_eq!(config.loans.loan_days, 14);
+        assert_eq!(config.ui.theme, "light");
+        assert_eq!(config.server.address.to_string(), "127.0.0.1:8080");
+        assert_eq!(config.server.token_minutes, 60);
+    }
+
+    #[test]
+    fn test_env_override() {
+        let env = |name: &str| match name {
+            "RLM_DATABASE_SALT" => Some("training_".to_string()),
+            "RLM_LOANS_MAX_LOANS" => Some("2".to_string()),
+            "RLM_SERVER_ADDRESS" => Some("0.0.0.0:9000".to_string()),
+            _ => None,
+        };
+        let config = Config::parse(MINIMAL, &env).unwrap();
+        assert_eq!(config.database.salt, "training_");
+        assert_eq!(config.loans.max_loans, 2);
+        assert_eq!(config.server.address.port(), 9000);
+    }
+
+    #[test]
+    fn test_errors_name_the_key() {
+        let missing = Config::parse("[DBSettings]\nendpoint = https://example.com\n", &no_env);
+        assert_eq!(
+            missing.unwrap_err().to_string(),
+            "Missing setting [DBSettings] api_key"
+        );
+
+        let bad = Config::parse(&format!("{}[Security]\nlockout_seconds = soon\n", MINIMAL), &no_env);
+        assert_eq!(
+            bad.unwrap_err().to_string(),
+            "Invalid value 'soon' for [Security] lockout_seconds: expected a whole number"
+        );
+
+        let fresh = Config::parse(TEMPLATE, &no_env).unwrap_err();
+        assert!(fresh.is_unconfigured());
+        let partial = Config::parse("[DBSettings]\nendpoint = https://example.com\n", &no_env);
+        assert!(!partial.unwrap_err().is_unconfigured());
+
+        let bad = Config::parse(&format!("{}[UI]\ntheme = blue\n", MINIMAL), &no_env);
+        assert!(matches!(bad, Err(ConfigError::Invalid { key: "theme", .. })));
+
+        let bad = Config::parse(&format!("{}[Server]\naddress = localhost\n", MINIMAL), &no_env);
+        assert!(matches!(bad, Err(ConfigError::Invalid { key: "address", .. })));
+
+        let bad = Config::parse(&format!("{}[Security]\ntotp_required_level = Admin\n", MINIMAL), &no_env);
+        assert!(matches!(bad, Err(ConfigError::Invalid { key: "totp_required_level", .. })));
+        let contents = format!("{}[Security]\ntotp_required_level = Admin\ntotp_key = secret\n", MINIMAL);
+        assert!(Config::parse(&contents, &no_env).is_ok());
+    }
+
+    #[test]
+    fn test_profiles() {
+        let contents = format!(
+            "{}profile = Training\n[profile.training]\nendpoint = https://training.example.com\napi_key = other\nsalt = training_\n",
+            MINIMAL
+        );
+        let config = Config::parse(&contents, &no_env).unwrap();
+        assert_eq!(config.active_profile, "training");
+        assert_eq!(config.database.endpoint, "https://training.example.com");
+        assert_eq!(config.profile("default").unwrap().endpoint, "https://example.com");
+        assert!(config.profile("staging").is_err());
+
+        let env = |name: &str| match name {
+            "RLM_DATABASE_PROFILE" => Some("default".to_string()),
+            "RLM_PROFILE_TRAINING_SALT" => Some("sandbox_".to_string()),
+            _ => None,
+        };
+        let config = Config::parse(&contents, &env).unwrap();
+        assert_eq!(config.database.salt, "");
+        assert_eq!(config.profile("training").unwrap().salt, "sandbox_");
+
+        let missing = Config::parse(&format!("{}profile = staging\n", MINIMAL), &no_env);
+        assert!(matches!(missing, Err(ConfigError::Invalid { key: "profile", .. })));
+        let incomplete = Config::parse(&format!("{}[profile.staging]\napi_key = x\n", MINIMAL), &no_env);
+        assert_eq!(
+            incomplete.unwrap_err().to_string(),
+            "Missing setting [profile.staging] endpoint"
+        );
+    }
+
+    #[test]
+    fn test_discovery_order() {
+        let dir = std::env::temp_dir().join(format!("rlm-discovery-{}", std::process::id()));
+        let bundled = dir.join("resources").join(CONFIG_FILE_NAME);
+        std::fs::create_dir_all(bundled.parent().unwrap()).unwrap();
+        std::fs::write(&bundled, MINIMAL).unwrap();
+
+        // Without an app config directory the bundled file is used in place
+        let mut locations = ConfigLocations {
+            resources: vec![bundled.clone()],
+            ..Default::default()
+        };
+        assert_eq!(locations.discover().unwrap(), bundled);
+
+        // Otherwise it is copied there on first run, and the copy is used from then on
+        locations.app_config_dir = Some(dir.join("app"));
+        let generated = locations.discover().unwrap();
+        assert_eq!(generated, dir.join("app").join(CONFIG_FILE_NAME));
+        assert_eq!(std::fs::read_to_string(&generated).unwrap(), MINIMAL);
+        std::fs::write(&generated, TEMPLATE).unwrap();
+        assert_eq!(locations.discover().unwrap(), generated);
+        assert_eq!(std::fs::read_to_string(&bundled).unwrap(), MINIMAL);
+
+        std::fs::remove_file(&generated).unwrap();
+        locations.resources.clear();
+        assert_eq!(locations.discover().unwrap(), generated);
+        assert_eq!(std::fs::read_to_string(&generated).unwrap(), TEMPLATE);
+
+        locations.env = Some(dir.join("missing.ini"));
+        assert!(locations.discover().is_err());
+        locations.cli = Some(bundled.clone());
+        assert_eq!(locations.discover().unwrap(), bundled);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
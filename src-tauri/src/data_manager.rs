@@ -1,4 +1,6 @@
-use crate::types::structs::DisplayInfo;
+use crate::config::DatabaseConfig;
+use crate::types::enums::AuditAction;
+use crate::types::structs::{AuditEntry, AuditFilter, DisplayInfo};
 use serde::de::DeserializeOwned;
 use std::fmt;
 
@@ -6,9 +8,9 @@ use serde::Serialize;
 use std::fmt::Display;
 
 pub mod manager {
-    use super::super::utils::network;
+    use crate::utils::{diff, loading, network};
     use super::*;
-    use postgrest::Postgrest;
+    use postgrest::{Builder, Postgrest};
 
     #[derive(Debug)]
     pub enum DbToolError {
@@ -16,30 +18,47 @@ pub mod manager {
         FailQuery,
         EntryExists,
         BadEntry,
+        BadSecret,
+        /// The database answered with an error, e.g. for an unknown column or a policy
+        Rejected(String),
     }
 
     impl Display for DbToolError {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-            match *self {
+            match self {
                 DbToolError::FailConnect => write!(f, "Invalid URL"),
                 DbToolError::FailQuery => write!(f, "Failed to execute query"),
                 DbToolError::EntryExists => write!(f, "Entry already exists"),
                 DbToolError::BadEntry => write!(f, "Entry does not exist"),
+                DbToolError::BadSecret => write!(f, "Failed to decrypt the API key"),
+                DbToolError::Rejected(body) => write!(f, "Database refused the request: {}", body),
             }
         }
     }
 
 
+    /// Fields whose values are never copied into the audit log.
+    const REDACTED_FIELDS: [&str; 5] = [
+        "password",
+        "password_history",
+        "reset_code",
+        "totp_secret",
+        "recovery_codes",
+    ];
+
     #[derive(Clone)]
     pub struct DbTool {
         salt: String,
         client: Postgrest,
+        actor: Option<u16>,
     }
     impl DbTool {
-        pub async fn new(args: &[String]) -> Result<DbTool, DbToolError> {
-            let endpoint = args[0].clone();
-            let api_key = args[1].clone();
-            let salt = args[2].clone();
+        pub async fn new(settings: &DatabaseConfig) -> Result<DbTool, DbToolError> {
+            let endpoint = settings.endpoint.clone();
+            // The key may be stored encrypted, it is only ever decrypted here
+            let api_key =
+                loading::reveal_setting(&settings.api_key).map_err(|_| DbToolError::BadSecret)?;
+            let salt = settings.salt.clone();
 
             let res = network::test_network(&endpoint).await;
             if !res {
@@ -48,116 +67,186 @@ pub mod manager {
 
             let client = Postgrest::new(&endpoint).insert_header("apikey", &api_key);
 
-            println!("Connecting to {endpoint}");
-            Ok(DbTool { salt, client })
-        }
-
-        pub async fn get_table<T: DisplayInfo + DeserializeOwned>(&self) -> Vec<T> {
-            let table_name = format!("{}{}", self.salt, T::get_table_name());
-            let resp = self
-                .client
-                .from(table_name)
-                .select("*")
-                .execute()
-                .await
-                .expect("Failed to execute query");
-            match resp.text().await {
-                Ok(s) => match serde_json::from_str(&s) {
-                    Ok(vec_t) => vec_t,
-                    Err(err) => panic!(
-                        "Error parsing data -> {}, most likely an invalid API url",
-                        err
-                    ),
-                },
-                Err(_) => panic!("Invalid table settings. Unable to get data."),
-            }
+            eprintln!("Connecting to {endpoint}");
+            Ok(DbTool {
+                salt,
+                client,
+                actor: None,
+            })
+        }
+
+        /// Sets the employee that subsequent changes are attributed to in the audit log.
+        pub fn set_actor(&mut self, employee_id: Option<u16>) {
+            self.actor = employee_id;
+        }
+
+        pub async fn get_table<T: DisplayInfo + DeserializeOwned>(&self) -> Result<Vec<T>, DbToolError> {
+            let body = send(self.table::<T>().select("*")).await?;
+            serde_json::from_str(&body).map_err(|_| DbToolError::FailQuery)
+        }
+
+        /// Checks that the table for `T` exists and can be read, returning its row count.
+        pub async fn count_rows<T: DisplayInfo>(&self) -> Result<usize, DbToolError> {
+            let body = send(self.table::<T>().select("id")).await?;
+            let rows: Vec<serde_json::Value> =
+                serde_json::from_str(&body).map_err(|_| DbToolError::FailQuery)?;
+            Ok(rows.len())
+        }
+
+        /// Returns the id after the highest one in use in the table for `T`.
+        pub async fn next_id<T: DisplayInfo>(&self) -> Result<u16, DbToolError> {
+            let body = send(self.table::<T>().select("id").order("id.desc").limit(1)).await?;
+            let rows: Vec<serde_json::Value> =
+                serde_json::from_str(&body).map_err(|_| DbToolError::FailQuery)?;
+            let highest = rows
+                .first()
+                .and_then(|row| row["id"].as_u64())
+                .unwrap_or(0);
+            u16::try_from(highest + 1).map_err(|_| DbToolError::FailQuery)
+        }
+
+        pub async fn database_get<T: DisplayInfo + DeserializeOwned>(
+            &self,
+            id: u16,
+        ) -> Result<Option<T>, DbToolError> {
+            let body = send(self.table::<T>().eq("id", id.to_string()).select("*")).await?;
+            let mut rows: Vec<T> = serde_json::from_str(&body).map_err(|_| DbToolError::FailQuery)?;
+            Ok(rows.pop())
+        }
+
+        pub async fn database_upsert<T: DisplayInfo + Serialize>(
+            &self,
+            obj: &T,
+        ) -> Result<(), DbToolError> {
+            let before = self.database_get::<T>(obj.get_id()).await?;
+            let body = serde_json::to_value(obj).unwrap();
+            send(self.table::<T>().upsert(body.to_string())).await?;
+            let action = match before {
+                Some(_) => AuditAction::Update,
+                None => AuditAction::Insert,
+            };
+            self.audit(action, before.as_ref(), Some(obj)).await
         }
 
         pub async fn database_insert<T: DisplayInfo + Serialize>(
             &self,
             obj: &T,
         ) -> Result<(), DbToolError> {
-            self.check_entry_exists::<T>(obj)
-                .await
-                .map_err(|_| DbToolError::EntryExists)?;
-            let table_name = format!("{}{}", self.salt, T::get_table_name());
+            self.check_entry_exists::<T>(obj).await?;
             let body = serde_json::to_value(obj).unwrap();
-            let resp = self
-                .client
-                .from(table_name)
-                .insert(body.to_string())
-                .execute()
-                .await;
-            match resp.expect("Unknown Error").text().await {
-                Ok(_) => Ok(()),
-                Err(_) => Err(DbToolError::FailConnect),
-            }
+            send(self.table::<T>().insert(body.to_string())).await?;
+            self.audit(AuditAction::Insert, None, Some(obj)).await
         }
 
         pub async fn database_update<T: DisplayInfo + Serialize>(
             &self,
             obj: &T,
         ) -> Result<(), DbToolError> {
-            if let Ok(()) = self.check_entry_exists::<T>(obj).await {
-                return Err(DbToolError::BadEntry);
-            }
+            self.database_update_as(obj, AuditAction::Update).await
+        }
 
-            let table_name = format!("{}{}", self.salt, T::get_table_name());
+        /// Updates `obj` and records the change in the audit log under `action`.
+        pub async fn database_update_as<T: DisplayInfo + Serialize>(
+            &self,
+            obj: &T,
+            action: AuditAction,
+        ) -> Result<(), DbToolError> {
+            let before = self
+                .database_get::<T>(obj.get_id())
+                .await?
+                .ok_or(DbToolError::BadEntry)?;
             let body = serde_json::to_value(obj).unwrap();
-            self.client
-                .from(table_name)
-                .eq("id", obj.get_id().to_string())
-                .update(body.to_string())
-                .execute()
-                .await
-                .expect("Unknown Error")
-                .text()
-                .await
-                .map_err(|_| DbToolError::EntryExists)?;
-            Ok(())
+            send(
+                self.table::<T>()
+                    .eq("id", obj.get_id().to_string())
+                    .update(body.to_string()),
+            )
+            .await?;
+            self.audit(action, Some(&before), Some(obj)).await
         }
 
         pub async fn database_delete<T: DisplayInfo + Serialize>(
             &self,
             obj: &T,
         ) -> Result<(), DbToolError> {
-            if let Ok(()) = self.check_entry_exists::<T>(obj).await {
-                return Err(DbToolError::BadEntry);
+            let before = self
+                .database_get::<T>(obj.get_id())
+                .await?
+                .ok_or(DbToolError::BadEntry)?;
+            send(self.table::<T>().eq("id", obj.get_id().to_string()).delete()).await?;
+            self.audit(AuditAction::Delete, Some(&before), None).await
+        }
+
+        /// A query on the table for `T`, with the salt applied.
+        fn table<T: DisplayInfo>(&self) -> Builder {
+            self.client.from(format!("{}{}", self.salt, T::get_table_name()))
+        }
+
+        pub async fn get_audit_log(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>, DbToolError> {
+            let table_name = format!("{}{}", self.salt, AuditEntry::get_table_name());
+            let mut query = self.client.from(table_name).select("*");
+            if let Some(employee_id) = filter.employee_id {
+                query = query.eq("employee_id", employee_id.to_string());
+            }
+            if let Some(table) = &filter.table_name {
+                query = query.eq("table_name", table);
+            }
+            if let Some(from) = filter.from {
+                query = query.gte("timestamp", from.to_string());
             }
-            let table_name = format!("{}{}", self.salt, T::get_table_name());
-            self.client
-                .from(table_name)
-                .eq("id", obj.get_id().to_string())
-                .delete()
-                .execute()
-                .await
-                .expect("Unknown Error")
-                .text()
-                .await
-                .map_err(|_| DbToolError::FailQuery)?;
+            if let Some(to) = filter.to {
+                query = query.lte("timestamp", to.to_string());
+            }
+            let body = send(query.order("timestamp.desc")).await?;
+            serde_json::from_str(&body).map_err(|_| DbToolError::FailQuery)
+        }
+
+        /// Appends an entry to the audit log. Entries are only ever inserted, never changed.
+        async fn audit<T: DisplayInfo + Serialize>(
+            &self,
+            action: AuditAction,
+            before: Option<&T>,
+            after: Option<&T>,
+        ) -> Result<(), DbToolError> {
+            if !T::audited() {
+                return Ok(());
+            }
+            let record_id = before.or(after).map_or(0, |obj| obj.get_id());
+            let before = before.map(|obj| serde_json::to_value(obj).unwrap());
+            let after = after.map(|obj| serde_json::to_value(obj).unwrap());
+            let changes = diff::json_diff(before.as_ref(), after.as_ref(), &REDACTED_FIELDS);
+            let entry = AuditEntry::new(self.actor, action, T::get_table_name(), record_id, changes);
+
+            let table_name = format!("{}{}", self.salt, AuditEntry::get_table_name());
+            let body = serde_json::to_value(&entry).unwrap();
+            send(self.client.from(table_name).insert(body.to_string())).await?;
             Ok(())
         }
 
         async fn check_entry_exists<T: DisplayInfo>(&self, obj: &T) -> Result<(), DbToolError> {
-            let table_name = format!("{}{}", self.salt, T::get_table_name());
-            let resp = self
-                .client
-                .from(table_name)
-                .eq("id", obj.get_id().to_string())
-                .execute()
-                .await;
-            match resp.expect("Unknown error").text().await {
-                Ok(body) => {
-                    if !body.contains("id") {
-                        Ok(())
-                    } else {
-                        Err(DbToolError::EntryExists)
-                    }
-                }
-                Err(_) => Err(DbToolError::FailQuery),
+            let body = send(self.table::<T>().eq("id", obj.get_id().to_string()).select("id")).await?;
+            let rows: Vec<serde_json::Value> =
+                serde_json::from_str(&body).map_err(|_| DbToolError::FailQuery)?;
+            if rows.is_empty() {
+                Ok(())
+            } else {
+                Err(DbToolError::EntryExists)
             }
         }
     }
+
+    /// Sends a request and returns the response body. PostgREST answers a rejected request,
+    /// e.g. for a missing column, a broken constraint or a row policy, with an error status
+    /// rather than a failed connection, so the status has to be checked as well.
+    async fn send(request: Builder) -> Result<String, DbToolError> {
+        let resp = request.execute().await.map_err(|_| DbToolError::FailConnect)?;
+        let status = resp.status();
+        let body = resp.text().await.map_err(|_| DbToolError::FailQuery)?;
+        if !status.is_success() {
+            return Err(DbToolError::Rejected(body));
+        }
+        Ok(body)
+    }
 }
 
 // Tests
@@ -209,8 +298,8 @@ mod tests {
 
     #[tokio::test]
     async fn add_employee() {
-        let settings = super::super::utils::loading::load_db_settings().unwrap();
-        let tool = manager::DbTool::new(&settings).await.unwrap();
+        let config = crate::config::Config::load().unwrap();
+        let tool = manager::DbTool::new(&config.database).await.unwrap();
 
         let test_employee = create_test_employee();
         assert!(tool
@@ -221,8 +310,8 @@ mod tests {
 
     #[tokio::test]
     async fn update_employee() {
-        let settings = super::super::utils::loading::load_db_settings().unwrap();
-        let tool = manager::DbTool::new(&settings).await.unwrap();
+        let config = crate::config::Config::load().unwrap();
+        let tool = manager::DbTool::new(&config.database).await.unwrap();
         let mut test_employee = create_test_employee();
         test_employee.set_name("Jane Doe".to_owned());
         assert!(tool
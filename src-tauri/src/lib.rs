@@ -0,0 +1,28 @@
+//! The core of Rusty Library Management: the domain model (`types`), storage on a PostgREST
+//! database (`data_manager`), configuration, and the services built on them (`app`, `setup`).
+//! The Tauri app is a thin layer of commands over this crate, as are `rlm-admin` and the HTTP
+//! API in `server`. `App` is async and shares its state behind async locks, so front ends
+//! hold it without a lock of their own.
+//!
+//! ```no_run
+//! use rusty_library_management::{App, Config};
+//!
+//! # async fn example() {
+//! let config = Config::load().expect("invalid config");
+//! let app = App::new(config).await.expect("failed to connect");
+//! app.run().await.expect("failed to load the tables");
+//! app.authenticate_employee(1, "password").await.unwrap();
+//! # }
+//! ```
+
+pub mod app;
+pub mod config;
+pub mod data_manager;
+pub mod server;
+pub mod setup;
+pub mod types;
+pub mod utils;
+
+pub use app::App;
+pub use config::Config;
+pub use data_manager::manager::{DbTool, DbToolError};
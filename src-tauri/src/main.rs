@@ -1,88 +1,183 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use rusty_library_management::types::enums::{AppEvent, MediaType, Table, TotpStatus};
+use rusty_library_management::types::structs::{
+    AuditEntry, AuditFilter, Branch, BudgetSummary, Department, EmployeeInfo, InterlibraryLoan,
+    Media, OrgChart, PartnerLibrary, Project, ProjectSpend, PurchaseRequest, RegistryMigration,
+    SearchResult, SpendReport, TableSchema, TotpEnrolment, Transfer, Vendor, VendorMerge,
+    VendorReport,
+};
+use rusty_library_management::{app, config, setup, utils};
+use serde_json::{Map, Value};
+use std::path::PathBuf;
 use std::sync::Mutex;
-use tauri::State;
-use types::structs::{DisplayInfo, Media};
+use tauri::{AppHandle, Invoke, Manager, State};
+use tokio::sync::broadcast::{self, error::RecvError};
 
-mod app;
-mod data_manager;
-mod types;
-mod utils;
+/// The Tauri event that `App` events reach the windows as.
+const APP_EVENT: &str = "app-event";
+
+/// The only commands that can run before setup has finished and `App` is managed.
+const SETUP_COMMANDS: [&str; 4] = [
+    "get_setup_status",
+    "test_connection",
+    "create_initial_dev",
+    "finish_setup",
+];
 
 #[tauri::command]
-async fn authenticate(
-    tool: State<'_, Mutex<app::App>>,
-    id: u16,
-    password: &str,
-) -> Result<bool, String> {
-    let mut app = tool.lock().map_err(|_| "Failed to acquire lock")?;
-    app.authenticate_employee(id, password)
+async fn authenticate(app: State<'_, app::App>, id: u16, password: &str) -> Result<bool, String> {
+    app.authenticate_employee(id, password).await
 }
 
-/// Converts a HashMap of Media objects to a Vec of Media objects.
-/// 
-/// # Arguments
-/// 
-/// * `media_guard` - A reference to the HashMap of Media objects.
-/// 
-/// # Returns
-/// 
-/// A Vec of Media objects.
-/// 
-/// # Example
-/// 
-/// ```
-/// use rusty_library_management::types::structs::Media;
-/// use rusty_library_management::utils::loading::hashmap_to_vec;
-/// use std::collections::HashMap;
-/// 
-/// let mut media_guard: HashMap<String, Media> = HashMap::new();
-/// // Add some Media objects to the HashMap...
-/// 
-/// let new_media: Vec<Media> = hashmap_to_vec(&media_guard);
-/// ```
 #[tauri::command]
-async fn get_media(tool: State<'_, Mutex<app::App>>) -> Result<String, String> {
-    let app = tool.lock().map_err(|_| "Failed to acquire lock")?;
-    let media_guard = app.get_media();
-    let new_media: Vec<types::structs::Media> = utils::loading::hashmap_to_vec(&*media_guard);
+async fn unlock_account(app: State<'_, app::App>, id: u16) -> Result<(), String> {
+    app.unlock_employee(id).await
+}
 
-    serde_json::to_string(&new_media).map_err(|_| "Failed to serialize media data".into())
+#[tauri::command]
+async fn change_password(
+    app: State<'_, app::App>,
+    old_password: &str,
+    new_password: &str,
+) -> Result<(), String> {
+    app.change_password(old_password, new_password).await
 }
 
 #[tauri::command]
-async fn search_media(tool: State<'_, Mutex<app::App>>, search: &str) -> Result<String, String> {
-    // TODO: Change the logic so that it creates a new map with the name, that way we get O(nlogn) instead of O(n^2log(n))
-    let app = tool.lock().map_err(|_| "Failed to acquire lock")?;
-    let mut media_vec: Vec<Media> = vec![];
+async fn issue_reset_code(app: State<'_, app::App>, id: u16) -> Result<String, String> {
+    app.issue_reset_code(id).await
+}
 
-    let names = app
-        .search_by_id::<Media>(search)
-        .ok_or_else(|| "No media found".to_string())?;
-    for med in app.get_media().values() {
-        let med_name = med.get_name().to_lowercase();
-        if names.iter().any(|n| n == &med_name)
-            && !media_vec.iter().any(|m| m.get_name() == med_name)
-        {
-            media_vec.push(med.clone());
-        }
+#[tauri::command]
+async fn password_change_required(app: State<'_, app::App>) -> Result<bool, String> {
+    Ok(app.password_change_required().await)
+}
+
+#[tauri::command]
+async fn get_totp_status(app: State<'_, app::App>) -> Result<TotpStatus, String> {
+    Ok(app.totp_status().await)
+}
+
+#[tauri::command]
+async fn verify_totp(app: State<'_, app::App>, code: &str) -> Result<bool, String> {
+    app.verify_totp(code).await
+}
+
+#[tauri::command]
+async fn begin_totp_enrolment(app: State<'_, app::App>) -> Result<TotpEnrolment, String> {
+    app.begin_totp_enrolment().await
+}
+
+#[tauri::command]
+async fn confirm_totp_enrolment(
+    app: State<'_, app::App>,
+    code: &str,
+) -> Result<Vec<String>, String> {
+    app.confirm_totp_enrolment(code).await
+}
+
+#[tauri::command]
+async fn get_ui_config(app: State<'_, app::App>) -> Result<config::UiConfig, String> {
+    Ok(app.get_ui_config().clone())
+}
+
+/// Encrypts a secret in config.ini. Leaving out `value` encrypts the value already there.
+#[tauri::command]
+async fn store_config_secret(
+    app: State<'_, app::App>,
+    section: &str,
+    setting: &str,
+    value: Option<&str>,
+) -> Result<(), String> {
+    app.store_config_secret(section, setting, value).await
+}
+
+#[tauri::command]
+async fn get_profiles(app: State<'_, app::App>) -> Result<config::ProfileList, String> {
+    app.get_profiles().await
+}
+
+/// Reconnects to another database profile. The current user is logged out afterwards.
+#[tauri::command]
+async fn switch_profile(app: State<'_, app::App>, name: &str) -> Result<(), String> {
+    app.switch_profile(name).await
+}
+
+/// Lists every media item in `App`'s in-memory copy of the Media table, in no particular
+/// order. The copy is reloaded by `refresh_all_data` and kept current by `App`'s own writes.
+#[tauri::command]
+async fn get_media(app: State<'_, app::App>) -> Result<Vec<Media>, String> {
+    let media_guard = app.get_media().await;
+    Ok(utils::loading::hashmap_to_vec(&*media_guard))
+}
+
+#[tauri::command]
+async fn get_current_employee(app: State<'_, app::App>) -> Result<EmployeeInfo, String> {
+    app.get_current_employee().await
+}
+
+#[tauri::command]
+async fn get_employees(app: State<'_, app::App>) -> Result<Vec<EmployeeInfo>, String> {
+    app.get_employees().await
+}
+
+#[tauri::command]
+async fn get_org_chart(app: State<'_, app::App>) -> Result<OrgChart, String> {
+    app.get_org_chart().await
+}
+
+/// Lists the reports of an employee, by default the current user's. `indirect` includes
+/// everyone further down the tree.
+#[tauri::command]
+async fn get_reports(
+    app: State<'_, app::App>,
+    employee_id: Option<u16>,
+    indirect: Option<bool>,
+) -> Result<Vec<EmployeeInfo>, String> {
+    app.get_reports(employee_id, indirect.unwrap_or(false)).await
+}
+
+#[tauri::command]
+async fn get_chain_of_command(
+    app: State<'_, app::App>,
+) -> Result<Vec<EmployeeInfo>, String> {
+    app.get_chain_of_command().await
+}
+
+#[tauri::command]
+async fn set_alloc_budget(
+    app: State<'_, app::App>,
+    employee_id: u16,
+    alloc_budget: u16,
+) -> Result<(), String> {
+    app.set_alloc_budget(employee_id, alloc_budget).await
+}
+
+#[tauri::command]
+async fn search_media(
+    app: State<'_, app::App>,
+    search: &str,
+    all_branches: Option<bool>,
+) -> Result<Vec<SearchResult>, String> {
+    let results = app.search_media(search, all_branches.unwrap_or(false)).await;
+    if results.is_empty() {
+        return Err("No media found".to_string());
     }
-    dbg!(&media_vec);
-    serde_json::to_string(&media_vec).map_err(|_| "Failed to serialize media data".into())
+    Ok(results)
 }
 
 /// This function is annotated with the `tauri::command` attribute and is called `get_rank`. 
-/// It takes a `State` parameter containing a `Mutex` wrapped instance of the `App` struct. 
-/// The function attempts to acquire a lock on the `App` instance and then calls the `get_permission_level` method on it.
+/// It takes a `State` parameter containing the shared instance of the `App` struct. 
+/// The function calls the `get_permission_level` method on it.
 /// If the method returns `Some`, it converts the result to a string and returns it. Otherwise, it returns an error message.
 ///
 /// # Example
 /// ```rust
 /// #[tauri::command]
-/// async fn get_rank(tool: State<'_, Mutex<app::App>>) -> Result<String, String> {
-///     let app = tool.lock().map_err(|_| "Failed to acquire lock")?;
-///     app.get_permission_level().map_or_else(
+/// async fn get_rank(app: State<'_, app::App>) -> Result<String, String> {
+///     app.get_permission_level().await.map_or_else(
 ///         || Err("Could not find user".into()),
 ///         |res| Ok(res.to_string()),
 ///     )
@@ -90,47 +185,516 @@ async fn search_media(tool: State<'_, Mutex<app::App>>, search: &str) -> Result<
 /// ```
 ///
 /// # Arguments
-/// - `tool`: A `State` parameter containing a `Mutex` wrapped instance of the `App` struct.
+/// - `app`: A `State` parameter containing the shared instance of the `App` struct.
 ///
 /// # Returns
 /// - If the `get_permission_level` method returns `Some`, the function returns the permission level as a string.
 /// - If the `get_permission_level` method returns `None`, the function returns an error message.
 #[tauri::command]
-async fn get_rank(tool: State<'_, Mutex<app::App>>) -> Result<String, String> {
-    let app = tool.lock().map_err(|_| "Failed to acquire lock")?;
-    app.get_permission_level().map_or_else(
+async fn get_rank(app: State<'_, app::App>) -> Result<String, String> {
+    app.get_permission_level().await.map_or_else(
         || Err("Could not find user".into()),
         |res| Ok(res.to_string()),
     )
 }
 
 
+/// Checks out every media id in `cart`, reporting the ones that failed together.
 #[tauri::command]
-async fn media_checkout(tool: State<'_, Mutex<app::App>>, cart: &str) -> Result<String, String> {
-    let cart_items: Vec<types::structs::Media> = serde_json::from_str(cart)
-        .map_err(|_| "Failed to parse cart data".to_string())?;
-    let media_ids: Vec<u16> = cart_items.into_iter().map(|item| item.get_id()).collect();
-    let mut app = tool.lock().map_err(|_| "Failed to acquire lock")?;
-    for media_id in media_ids {
-        _ = app.rent_media(media_id);
+async fn media_checkout(app: State<'_, app::App>, cart: Vec<u16>) -> Result<(), String> {
+    let mut errors = Vec::new();
+    for media_id in cart {
+        if let Err(e) = app.rent_media(media_id).await {
+            errors.push(e);
+        }
     }
-    Ok("Checkout successful".to_string())
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn media_return(app: State<'_, app::App>, id: u16) -> Result<(), String> {
+    app.return_media(id).await
+}
+
+#[tauri::command]
+async fn get_branches(app: State<'_, app::App>) -> Result<Vec<Branch>, String> {
+    app.get_branches().await
+}
+
+#[tauri::command]
+async fn save_branch(app: State<'_, app::App>, branch: Branch) -> Result<(), String> {
+    app.save_branch(branch).await
+}
+
+/// Lists transfers to or from the current employee's branch, or every transfer with
+/// `all_branches`.
+#[tauri::command]
+async fn get_transfers(
+    app: State<'_, app::App>,
+    all_branches: Option<bool>,
+) -> Result<Vec<Transfer>, String> {
+    app.get_transfers(all_branches.unwrap_or(false)).await
+}
+
+#[tauri::command]
+async fn request_transfer(
+    app: State<'_, app::App>,
+    media_id: u16,
+    destination_branch: u16,
+) -> Result<Transfer, String> {
+    app.request_transfer(media_id, destination_branch).await
+}
+
+#[tauri::command]
+async fn dispatch_transfer(app: State<'_, app::App>, id: u16) -> Result<Transfer, String> {
+    app.dispatch_transfer(id).await
+}
+
+#[tauri::command]
+async fn receive_transfer(app: State<'_, app::App>, id: u16) -> Result<Transfer, String> {
+    app.receive_transfer(id).await
+}
+
+#[tauri::command]
+async fn get_partner_libraries(app: State<'_, app::App>) -> Result<Vec<PartnerLibrary>, String> {
+    app.get_partner_libraries().await
+}
+
+#[tauri::command]
+async fn save_partner_library(
+    app: State<'_, app::App>,
+    partner: PartnerLibrary,
+) -> Result<(), String> {
+    app.save_partner_library(partner).await
+}
+
+#[tauri::command]
+async fn get_interlibrary_loans(
+    app: State<'_, app::App>,
+    include_closed: Option<bool>,
+) -> Result<Vec<InterlibraryLoan>, String> {
+    app.get_interlibrary_loans(include_closed.unwrap_or(false)).await
+}
+
+#[tauri::command]
+async fn request_interlibrary_loan(
+    app: State<'_, app::App>,
+    partner_id: u16,
+    title: String,
+    media_type: MediaType,
+    patron: Option<String>,
+) -> Result<InterlibraryLoan, String> {
+    app.request_interlibrary_loan(partner_id, title, media_type, patron).await
+}
+
+/// Moves an interlibrary loan to its next status. `partner_due` is required when the item
+/// arrives and `patron` when it is lent out, unless the request already named one.
+#[tauri::command]
+async fn advance_interlibrary_loan(
+    app: State<'_, app::App>,
+    id: u16,
+    partner_due: Option<u64>,
+    patron: Option<String>,
+) -> Result<InterlibraryLoan, String> {
+    app.advance_interlibrary_loan(id, partner_due, patron).await
+}
+
+#[tauri::command]
+async fn get_vendors(app: State<'_, app::App>) -> Result<Vec<Vendor>, String> {
+    app.get_vendors().await
+}
+
+#[tauri::command]
+async fn save_vendor(app: State<'_, app::App>, vendor: Vendor) -> Result<(), String> {
+    app.save_vendor(vendor).await
+}
+
+/// Links media that only have a free-text vendor to vendor records, creating vendors for
+/// names that do not match an existing one.
+#[tauri::command]
+async fn migrate_vendors(app: State<'_, app::App>) -> Result<VendorMerge, String> {
+    app.migrate_vendors().await
+}
+
+#[tauri::command]
+async fn get_vendor_report(app: State<'_, app::App>) -> Result<Vec<VendorReport>, String> {
+    app.get_vendor_report().await
+}
+
+#[tauri::command]
+async fn get_departments(app: State<'_, app::App>) -> Result<Vec<Department>, String> {
+    app.get_departments().await
 }
 
+#[tauri::command]
+async fn save_department(
+    app: State<'_, app::App>,
+    department: Department,
+) -> Result<(), String> {
+    app.save_department(department).await
+}
+
+#[tauri::command]
+async fn delete_department(app: State<'_, app::App>, id: u16) -> Result<(), String> {
+    app.delete_department(id).await
+}
+
+#[tauri::command]
+async fn get_projects(app: State<'_, app::App>) -> Result<Vec<Project>, String> {
+    app.get_projects().await
+}
+
+#[tauri::command]
+async fn save_project(app: State<'_, app::App>, project: Project) -> Result<(), String> {
+    app.save_project(project).await
+}
+
+#[tauri::command]
+async fn delete_project(app: State<'_, app::App>, id: u16) -> Result<(), String> {
+    app.delete_project(id).await
+}
+
+/// Moves an employee to another department and project. Leaving one out clears it.
+#[tauri::command]
+async fn assign_employee(
+    app: State<'_, app::App>,
+    employee_id: u16,
+    department_id: Option<u16>,
+    project_id: Option<u16>,
+) -> Result<(), String> {
+    app.assign_employee(employee_id, department_id, project_id).await
+}
+
+/// Creates department and project records from the names employees already use.
+#[tauri::command]
+async fn migrate_departments(app: State<'_, app::App>) -> Result<RegistryMigration, String> {
+    app.migrate_departments().await
+}
+
+#[tauri::command]
+async fn get_project_spending(app: State<'_, app::App>) -> Result<Vec<ProjectSpend>, String> {
+    app.get_project_spending().await
+}
+
+/// Shows the current user's budget, or another employee's for Managers.
+#[tauri::command]
+async fn get_budget(
+    app: State<'_, app::App>,
+    employee_id: Option<u16>,
+) -> Result<BudgetSummary, String> {
+    app.get_budget(employee_id).await
+}
+
+#[tauri::command]
+async fn get_purchase_requests(
+    app: State<'_, app::App>,
+) -> Result<Vec<PurchaseRequest>, String> {
+    app.get_purchase_requests().await
+}
+
+#[tauri::command]
+async fn request_purchase(
+    app: State<'_, app::App>,
+    title: String,
+    media_type: MediaType,
+    vendor_id: u16,
+    price: u16,
+) -> Result<PurchaseRequest, String> {
+    app.request_purchase(title, media_type, vendor_id, price).await
+}
+
+#[tauri::command]
+async fn decide_purchase(
+    app: State<'_, app::App>,
+    id: u16,
+    approve: bool,
+) -> Result<PurchaseRequest, String> {
+    app.decide_purchase(id, approve).await
+}
+
+#[tauri::command]
+async fn receive_purchase(app: State<'_, app::App>, id: u16) -> Result<PurchaseRequest, String> {
+    app.receive_purchase(id).await
+}
+
+/// Totals approved and received purchases per department, or per project with `by_project`.
+#[tauri::command]
+async fn get_spend_report(
+    app: State<'_, app::App>,
+    by_project: Option<bool>,
+) -> Result<Vec<SpendReport>, String> {
+    app.get_spend_report(by_project.unwrap_or(false)).await
+}
+
+#[tauri::command]
+async fn get_audit_log(
+    app: State<'_, app::App>,
+    employee_id: Option<u16>,
+    table_name: Option<String>,
+    from: Option<u64>,
+    to: Option<u64>,
+) -> Result<Vec<AuditEntry>, String> {
+    let filter = AuditFilter {
+        employee_id,
+        table_name,
+        from,
+        to,
+    };
+    app.get_audit_log(&filter).await
+}
+
+#[tauri::command]
+async fn list_records(app: State<'_, app::App>, table: Table) -> Result<Vec<Value>, String> {
+    app.list_records(table).await
+}
+
+#[tauri::command]
+async fn get_record(app: State<'_, app::App>, table: Table, id: u16) -> Result<Value, String> {
+    app.read_record(table, id).await
+}
+
+/// Creates a row in any registered table. Fields that are left out keep their defaults.
+#[tauri::command]
+async fn create_record(
+    app: State<'_, app::App>,
+    table: Table,
+    fields: Map<String, Value>,
+) -> Result<Value, String> {
+    app.create_record(table, fields).await
+}
+
+/// Changes only the given fields of a row and returns the updated row.
+#[tauri::command]
+async fn update_record(
+    app: State<'_, app::App>,
+    table: Table,
+    id: u16,
+    changes: Map<String, Value>,
+) -> Result<Value, String> {
+    app.update_record(table, id, changes).await
+}
+
+/// Lists the fields of a table with their types, choices and rules, for building edit forms.
+#[tauri::command]
+async fn get_table_schema(app: State<'_, app::App>, table: Table) -> Result<TableSchema, String> {
+    app.get_table_schema(table).await
+}
+
+#[tauri::command]
+async fn delete_record(app: State<'_, app::App>, table: Table, id: u16) -> Result<(), String> {
+    app.delete_record(table, id).await
+}
+
+#[tauri::command]
+async fn get_setup_status(setup: State<'_, Mutex<setup::Setup>>) -> Result<setup::SetupStatus, String> {
+    let setup = setup.lock().map_err(|_| "Failed to acquire lock")?;
+    Ok(setup.status())
+}
+
+/// Checks candidate connection settings. A successful test is remembered for the later steps.
+#[tauri::command]
+async fn test_connection(
+    setup: State<'_, Mutex<setup::Setup>>,
+    endpoint: String,
+    api_key: String,
+    salt: String,
+) -> Result<setup::TableReport, String> {
+    setup
+        .lock()
+        .map_err(|_| "Failed to acquire lock")?
+        .ensure_pending()?;
+    let settings = config::DatabaseConfig {
+        endpoint,
+        api_key,
+        salt,
+    };
+    let (db, report) = setup::test_connection(&settings).await?;
+    setup
+        .lock()
+        .map_err(|_| "Failed to acquire lock")?
+        .set_connection(settings, db);
+    Ok(report)
+}
+
+#[tauri::command]
+async fn create_initial_dev(
+    setup: State<'_, Mutex<setup::Setup>>,
+    id: u16,
+    name: String,
+    password: String,
+) -> Result<(), String> {
+    let db = setup
+        .lock()
+        .map_err(|_| "Failed to acquire lock")?
+        .connection()?;
+    setup::create_initial_dev(&db, id, name, password).await
+}
+
+/// Saves the tested settings and starts the app without a restart.
+#[tauri::command]
+async fn finish_setup(
+    handle: AppHandle,
+    setup: State<'_, Mutex<setup::Setup>>,
+) -> Result<(), String> {
+    setup
+        .lock()
+        .map_err(|_| "Failed to acquire lock")?
+        .save_config()?;
+    let app = start_app().await?;
+    forward_events(handle.clone(), app.subscribe());
+    handle.manage(app);
+    setup
+        .lock()
+        .map_err(|_| "Failed to acquire lock")?
+        .complete();
+    Ok(())
+}
+
+/// Sends every `App` event on to all windows. Windows that missed events because they were
+/// coming in too fast are told to reload.
+fn forward_events(handle: AppHandle, mut events: broadcast::Receiver<AppEvent>) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(_)) => AppEvent::Reloaded,
+                Err(RecvError::Closed) => break,
+            };
+            if let Err(e) = handle.emit_all(APP_EVENT, event) {
+                eprintln!("Failed to forward {}: {}", APP_EVENT, e);
+            }
+        }
+    });
+}
+
+async fn start_app() -> Result<app::App, String> {
+    let config = config::Config::load().map_err(|e| e.to_string())?;
+    let app = app::App::new(config).await?;
+    app.run().await?;
+    Ok(app)
+}
 
 fn main() {
-    let app = Mutex::new(app::App::new());
-    app.lock().unwrap().run();
-
-    tauri::Builder::default()
-        .manage(app)
-        .invoke_handler(tauri::generate_handler![
-            authenticate,
-            get_rank,
-            get_media,
-            search_media,
-            media_checkout,
-        ])
-        .run(tauri::generate_context!())
+    let context = tauri::generate_context!();
+    let bundled = tauri::api::path::resource_dir(context.package_info(), &tauri::Env::default())
+        .map(|dir| dir.join("resources").join(config::CONFIG_FILE_NAME));
+    let locations = config::ConfigLocations::from_process(
+        tauri::api::path::app_config_dir(context.config()),
+        bundled
+            .into_iter()
+            .chain([PathBuf::from("./resources").join(config::CONFIG_FILE_NAME)])
+            .collect(),
+    );
+    let path = locations.discover().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    println!("Using config file {}", path.display());
+    utils::loading::set_config_file(path);
+
+    let mut builder = tauri::Builder::default();
+    // Without a configured database only the setup commands are available until setup finishes
+    match tauri::async_runtime::block_on(start_app()) {
+        Ok(app) => {
+            let events = app.subscribe();
+            builder = builder
+                .manage(app)
+                .manage(Mutex::new(setup::Setup::new(None)))
+                .setup(move |tauri_app| {
+                    forward_events(tauri_app.handle(), events);
+                    Ok(())
+                });
+        }
+        Err(e) if setup::first_run() => {
+            eprintln!("{}", e);
+            println!("Waiting for setup to be completed");
+            builder = builder.manage(Mutex::new(setup::Setup::new(Some(e))));
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    let handler = tauri::generate_handler![
+        get_setup_status,
+        test_connection,
+        create_initial_dev,
+        finish_setup,
+        authenticate,
+        unlock_account,
+        change_password,
+        issue_reset_code,
+        password_change_required,
+        get_totp_status,
+        verify_totp,
+        begin_totp_enrolment,
+        confirm_totp_enrolment,
+        store_config_secret,
+        get_ui_config,
+        get_profiles,
+        switch_profile,
+        get_rank,
+        get_media,
+        get_current_employee,
+        get_employees,
+        get_org_chart,
+        get_reports,
+        get_chain_of_command,
+        set_alloc_budget,
+        search_media,
+        media_checkout,
+        media_return,
+        get_branches,
+        save_branch,
+        get_transfers,
+        request_transfer,
+        dispatch_transfer,
+        receive_transfer,
+        get_partner_libraries,
+        save_partner_library,
+        get_interlibrary_loans,
+        request_interlibrary_loan,
+        advance_interlibrary_loan,
+        get_budget,
+        get_purchase_requests,
+        request_purchase,
+        decide_purchase,
+        receive_purchase,
+        get_spend_report,
+        get_vendors,
+        save_vendor,
+        migrate_vendors,
+        get_vendor_report,
+        get_departments,
+        save_department,
+        delete_department,
+        get_projects,
+        save_project,
+        delete_project,
+        assign_employee,
+        migrate_departments,
+        get_project_spending,
+        get_audit_log,
+        list_records,
+        get_record,
+        create_record,
+        update_record,
+        delete_record,
+        get_table_schema,
+    ];
+    builder
+        .invoke_handler(move |invoke: Invoke| {
+            let ready = invoke.message.window().try_state::<app::App>().is_some();
+            if !ready && !SETUP_COMMANDS.contains(&invoke.message.command()) {
+                invoke.resolver.reject("Finish setup first");
+                return;
+            }
+            handler(invoke)
+        })
+        .run(context)
         .expect("error while running tauri application");
 }
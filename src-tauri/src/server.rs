@@ -0,0 +1,455 @@
+use crate::app::App;
+use crate::config::ServerConfig;
+use crate::types::enums::{Table, TotpStatus};
+use crate::types::structs::{Employee, EmployeeInfo, LoginRecord, Media};
+use crate::utils;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::Read;
+use std::net::IpAddr;
+use tiny_http::{Header, Method, Request, Response};
+use tokio::sync::mpsc;
+
+/// Request bodies larger than this are refused.
+const MAX_BODY_BYTES: u64 = 1 << 20;
+
+/// Serves `App` over a REST API for clients that do not run the desktop app, e.g. kiosks.
+/// `App` has a single signed-in session, which each request switches to its token's
+/// employee, so requests are handled one at a time. Failed logins are counted per client
+/// address, on top of the lockout of each account.
+pub struct Server {
+    app: App,
+    config: ServerConfig,
+    sessions: HashMap<String, Session>,
+    throttles: HashMap<IpAddr, LoginRecord>,
+}
+
+/// A token handed out by `POST /api/login`.
+struct Session {
+    employee_id: u16,
+    expires: u64,
+    /// The account's `session_stamp` at login, the token stops working when it changes
+    stamp: String,
+}
+
+#[derive(Debug, PartialEq)]
+enum Route {
+    Login,
+    Logout,
+    Me,
+    Media,
+    Search,
+    Checkout(u16),
+    Return(u16),
+    List(Table),
+    Get(Table, u16),
+    Create(Table),
+    Update(Table, u16),
+    Delete(Table, u16),
+}
+
+#[derive(Deserialize)]
+struct Login {
+    id: u16,
+    password: String,
+    #[serde(default)]
+    code: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+struct ApiError {
+    status: u16,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: u16, message: &str) -> ApiError {
+        ApiError {
+            status,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// `App` reports every failure as a message, so the status code is picked from its wording.
+impl From<String> for ApiError {
+    fn from(message: String) -> Self {
+        let status = if message == "Insufficient permissions" {
+            403
+        } else if message.ends_with("not found") {
+            404
+        } else if message.starts_with("Failed to") {
+            500
+        } else {
+            400
+        };
+        ApiError { status, message }
+    }
+}
+
+type ApiResult = Result<Value, ApiError>;
+
+/// Listens on the configured address until the process is stopped.
+pub async fn run(app: App, config: ServerConfig) -> Result<(), String> {
+    let http = tiny_http::Server::http(config.address).map_err(|e| e.to_string())?;
+    eprintln!("Listening on http://{}", config.address);
+    let (sender, mut requests) = mpsc::channel(64);
+    // tiny_http blocks while it waits for connections, so it gets a thread of its own
+    std::thread::spawn(move || {
+        for request in http.incoming_requests() {
+            if sender.blocking_send(request).is_err() {
+                break;
+            }
+        }
+    });
+    let mut server = Server::new(app, config);
+    while let Some(request) = requests.recv().await {
+        server.respond(request).await;
+    }
+    Ok(())
+}
+
+impl Server {
+    pub fn new(app: App, config: ServerConfig) -> Server {
+        Server {
+            app,
+            config,
+            sessions: HashMap::new(),
+            throttles: HashMap::new(),
+        }
+    }
+
+    async fn respond(&mut self, mut request: Request) {
+        let (status, body) = if *request.method() == Method::Options {
+            (204, Value::Null)
+        } else {
+            match self.handle(&mut request).await {
+                Ok(body) => (200, body),
+                Err(e) => (e.status, json!({ "error": e.message })),
+            }
+        };
+        let body = if body.is_null() {
+            String::new()
+        } else {
+            body.to_string()
+        };
+        let mut response = Response::from_string(body)
+            .with_status_code(status)
+            .with_header(header("Content-Type", "application/json"));
+        if !self.config.allowed_origin.is_empty() {
+            response.add_header(header(
+                "Access-Control-Allow-Origin",
+                &self.config.allowed_origin,
+            ));
+            response.add_header(header(
+                "Access-Control-Allow-Headers",
+                "Authorization, Content-Type",
+            ));
+            response.add_header(header(
+                "Access-Control-Allow-Methods",
+                "GET, POST, PATCH, DELETE, OPTIONS",
+            ));
+        }
+        if let Err(e) = request.respond(response) {
+            eprintln!("Failed to send response: {}", e);
+        }
+    }
+
+    async fn handle(&mut self, request: &mut Request) -> ApiResult {
+        let url = request.url().to_string();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+        let route =
+            route(request.method(), path).ok_or_else(|| ApiError::new(404, "No such endpoint"))?;
+        let mut body = String::new();
+        request
+            .as_reader()
+            .take(MAX_BODY_BYTES)
+            .read_to_string(&mut body)
+            .map_err(|_| ApiError::new(400, "Invalid request body"))?;
+
+        if route == Route::Login {
+            let client = request.remote_addr().map(|addr| addr.ip());
+            let attempts = client
+                .and_then(|ip| self.throttles.remove(&ip))
+                .unwrap_or_default();
+            self.app.swap_login_attempts(attempts).await;
+            let result = self.login(&body).await;
+            let attempts = self.app.swap_login_attempts(LoginRecord::default()).await;
+            self.app.logout().await;
+
+            let now = utils::time::now();
+            let throttled =
+                |record: &LoginRecord| record.failed_attempts() > 0 || record.is_locked(now);
+            self.throttles.retain(|_, record| throttled(record));
+            if let Some(ip) = client.filter(|_| throttled(&attempts)) {
+                self.throttles.insert(ip, attempts);
+            }
+            return result;
+        }
+
+        let token = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Authorization"))
+            .and_then(|h| h.value.as_str().strip_prefix("Bearer "))
+            .map(str::to_string)
+            .ok_or_else(|| ApiError::new(401, "Missing token"))?;
+        let (employee_id, stamp) = self.check_token(&token)?;
+        if route == Route::Logout {
+            self.sessions.remove(&token);
+            return Ok(json!({}));
+        }
+
+        if let Err(e) = self.app.resume_session(employee_id, &stamp).await {
+            self.sessions.remove(&token);
+            return Err(ApiError::new(401, &e));
+        }
+        let result = self.dispatch(route, &parse_query(query), &body).await;
+        self.app.logout().await;
+        result
+    }
+
+    /// Signs in with the same checks as the desktop app. Accounts with two-factor
+    /// authentication send their code along with the password.
+    async fn login(&mut self, body: &str) -> ApiResult {
+        let login: Login =
+            serde_json::from_str(body).map_err(|e| ApiError::new(400, &e.to_string()))?;
+        if !self.app.authenticate_employee(login.id, &login.password).await? {
+            return Err(ApiError::new(401, "Invalid id or password"));
+        }
+        match self.app.totp_status().await {
+            TotpStatus::Verified => {}
+            TotpStatus::CodeRequired => {
+                let code = login
+                    .code
+                    .ok_or_else(|| ApiError::new(401, "Two-factor code required"))?;
+                if !self.app.verify_totp(&code).await? {
+                    return Err(ApiError::new(401, "Invalid two-factor code"));
+                }
+            }
+            TotpStatus::EnrolmentRequired => {
+                return Err(ApiError::new(
+                    403,
+                    "Set up two-factor authentication in the app first",
+                ))
+            }
+        }
+        if self.app.password_change_required().await {
+            return Err(ApiError::new(403, "Password change required"));
+        }
+
+        let stamp = self.app.session_stamp().await?;
+        let now = utils::time::now();
+        self.sessions.retain(|_, session| session.expires > now);
+        let token = utils::security::generate_code(32);
+        self.sessions.insert(
+            token.clone(),
+            Session {
+                employee_id: login.id,
+                expires: now + self.token_secs(),
+                stamp,
+            },
+        );
+        Ok(json!({ "token": token, "expires_in": self.token_secs() }))
+    }
+
+    /// Returns the employee a token belongs to with its stamp, and extends its lifetime.
+    fn check_token(&mut self, token: &str) -> Result<(u16, String), ApiError> {
+        let now = utils::time::now();
+        let lifetime = self.token_secs();
+        match self.sessions.get_mut(token) {
+            Some(session) if session.expires > now => {
+                session.expires = now + lifetime;
+                Ok((session.employee_id, session.stamp.clone()))
+            }
+            Some(_) => {
+                self.sessions.remove(token);
+                Err(ApiError::new(401, "Token expired"))
+            }
+            None => Err(ApiError::new(401, "Invalid token")),
+        }
+    }
+
+    fn token_secs(&self) -> u64 {
+        self.config.token_minutes as u64 * 60
+    }
+
+    async fn dispatch(
+        &mut self,
+        route: Route,
+        query: &HashMap<String, String>,
+        body: &str,
+    ) -> ApiResult {
+        let app = &self.app;
+        match route {
+            Route::Me => Ok(json!(app.get_current_employee().await?)),
+            Route::Media => Ok(json!(utils::loading::hashmap_to_vec(&*app.get_media().await))),
+            Route::Search => {
+                let search = query.get("q").map(String::as_str).unwrap_or_default();
+                let all_branches = query.get("all_branches").is_some_and(|v| v == "true");
+                Ok(json!(app.search_media(search, all_branches).await))
+            }
+            Route::Checkout(id) => {
+                app.rent_media(id).await?;
+                Ok(json!(app.get_media().await.get(&id)))
+            }
+            Route::Return(id) => {
+                app.return_media(id).await?;
+                Ok(json!(app.get_media().await.get(&id)))
+            }
+            Route::List(Table::Employee) => {
+                let employees = app.export_table::<Employee>().await?;
+                Ok(json!(employees.iter().map(EmployeeInfo::from).collect::<Vec<_>>()))
+            }
+            Route::List(Table::Media) => Ok(json!(app.export_table::<Media>().await?)),
+            Route::Get(Table::Employee, id) => {
+                Ok(employee_json(&app.get_obj::<Employee>(id).await?))
+            }
+            Route::Get(Table::Media, id) => Ok(json!(app.get_obj::<Media>(id).await?)),
+            Route::Create(table) => Ok(app.create_record(table, parse(body)?).await?),
+            Route::Update(table, id) => Ok(app.update_record(table, id, parse(body)?).await?),
+            Route::Delete(table, id) => {
+                app.delete_record(table, id).await?;
+                Ok(json!({}))
+            }
+            // `route` only hands out the employee and media tables
+            Route::List(_) | Route::Get(..) => Err(ApiError::new(404, "Not found")),
+            Route::Login | Route::Logout => unreachable!(),
+        }
+    }
+}
+
+/// Employees are returned without their password and two-factor secrets.
+fn employee_json(employee: &Employee) -> Value {
+    json!(EmployeeInfo::from(employee))
+}
+
+fn parse<T: serde::de::DeserializeOwned>(body: &str) -> Result<T, ApiError> {
+    serde_json::from_str(body).map_err(|e| ApiError::new(400, &e.to_string()))
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field.as_bytes(), value.as_bytes()).unwrap()
+}
+
+fn route(method: &Method, path: &str) -> Option<Route> {
+    let segments: Vec<&str> = path.trim_end_matches('/').split('/').skip(1).collect();
+    let table = |name: &str| match name {
+        "employees" => Some(Table::Employee),
+        "media" => Some(Table::Media),
+        _ => None,
+    };
+    let id = |id: &str| id.parse::<u16>().ok();
+    let route = match (method, segments.as_slice()) {
+        (Method::Post, ["api", "login"]) => Route::Login,
+        (Method::Post, ["api", "logout"]) => Route::Logout,
+        (Method::Get, ["api", "me"]) => Route::Me,
+        (Method::Get, ["api", "media"]) => Route::Media,
+        (Method::Get, ["api", "media", "search"]) => Route::Search,
+        (Method::Post, ["api", "media", media_id, "checkout"]) => Route::Checkout(id(media_id)?),
+        (Method::Post, ["api", "media", media_id, "return"]) => Route::Return(id(media_id)?),
+        (Method::Get, ["api", "admin", name]) => Route::List(table(name)?),
+        (Method::Post, ["api", "admin", name]) => Route::Create(table(name)?),
+        (Method::Get, ["api", "admin", name, record]) => Route::Get(table(name)?, id(record)?),
+        (Method::Patch, ["api", "admin", name, record]) => Route::Update(table(name)?, id(record)?),
+        (Method::Delete, ["api", "admin", name, record]) => {
+            Route::Delete(table(name)?, id(record)?)
+        }
+        _ => return None,
+    };
+    Some(route)
+}
+
+/// Splits a query string into decoded key/value pairs.
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(key), decode(value))
+        })
+        .collect()
+}
+
+/// Decodes `+` and `%XX` escapes. Invalid escapes are kept as they are.
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+            (b'+', _) => decoded.push(b' '),
+            (byte, _) => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// Tests
+// ---------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes() {
+        assert_eq!(route(&Method::Post, "/api/login"), Some(Route::Login));
+        assert_eq!(route(&Method::Get, "/api/media/"), Some(Route::Media));
+        assert_eq!(
+            route(&Method::Post, "/api/media/12/checkout"),
+            Some(Route::Checkout(12))
+        );
+        assert_eq!(
+            route(&Method::Patch, "/api/admin/employees/3"),
+            Some(Route::Update(Table::Employee, 3))
+        );
+        assert_eq!(
+            route(&Method::Get, "/api/admin/media"),
+            Some(Route::List(Table::Media))
+        );
+
+        assert_eq!(route(&Method::Get, "/api/login"), None);
+        assert_eq!(route(&Method::Post, "/api/media/abc/checkout"), None);
+        assert_eq!(route(&Method::Get, "/api/admin/vendors"), None);
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let query = parse_query("q=the+last%20of%2Fus&all_branches=true&empty");
+        assert_eq!(query["q"], "the last of/us");
+        assert_eq!(query["all_branches"], "true");
+        assert_eq!(query["empty"], "");
+        assert_eq!(decode("100%"), "100%");
+        assert_eq!(decode("%zz"), "%zz");
+    }
+
+    #[test]
+    fn test_error_status() {
+        assert_eq!(
+            ApiError::from("Insufficient permissions".to_string()).status,
+            403
+        );
+        assert_eq!(ApiError::from("Media not found".to_string()).status, 404);
+        assert_eq!(
+            ApiError::from("Failed to acquire lock".to_string()).status,
+            500
+        );
+        assert_eq!(
+            ApiError::from("Sonic is not available".to_string()).status,
+            400
+        );
+    }
+}
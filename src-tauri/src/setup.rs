@@ -0,0 +1,144 @@
+use crate::config::{Config, DatabaseConfig};
+use crate::data_manager::manager::DbTool;
+use crate::types::enums::PermissionLevel;
+use crate::types::structs::{Employee, Media};
+use crate::utils;
+use serde::Serialize;
+use ts_rs::TS;
+
+/// Backs the first-run wizard while the config file has no database yet. Once setup is
+/// complete every step is refused, so the wizard cannot be used to repoint a running app.
+#[derive(Default)]
+pub struct Setup {
+    complete: bool,
+    startup_error: Option<String>,
+    connection: Option<(DatabaseConfig, DbTool)>,
+}
+
+#[derive(Serialize, TS, Debug, Clone)]
+#[ts(export_to = "../src/bindings/")]
+pub struct SetupStatus {
+    pub required: bool,
+    pub error: Option<String>,
+    pub connection_tested: bool,
+}
+
+#[derive(Serialize, TS, Debug, Clone)]
+#[ts(export_to = "../src/bindings/")]
+pub struct TableReport {
+    pub employees: usize,
+    pub media: usize,
+}
+
+impl Setup {
+    pub fn new(startup_error: Option<String>) -> Setup {
+        Setup {
+            complete: startup_error.is_none(),
+            startup_error,
+            connection: None,
+        }
+    }
+
+    pub fn status(&self) -> SetupStatus {
+        SetupStatus {
+            required: !self.complete,
+            error: self.startup_error.clone(),
+            connection_tested: self.connection.is_some(),
+        }
+    }
+
+    pub fn ensure_pending(&self) -> Result<(), String> {
+        if self.complete {
+            return Err("Setup has already been completed".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn set_connection(&mut self, settings: DatabaseConfig, db: DbTool) {
+        self.connection = Some((settings, db));
+    }
+
+    /// Returns the last connection that passed `test_connection`.
+    pub fn connection(&self) -> Result<DbTool, String> {
+        self.ensure_pending()?;
+        self.connection
+            .as_ref()
+            .map(|(_, db)| db.clone())
+            .ok_or_else(|| "Test the database connection first".to_string())
+    }
+
+    /// Writes the tested connection settings to the config file. The API key is encrypted
+    /// with the machine key. Settings that are already there are never replaced.
+    pub fn save_config(&self) -> Result<(), String> {
+        self.ensure_pending()?;
+        if !first_run() {
+            return Err("A database is already configured, change it in config.ini".to_string());
+        }
+        let (settings, _) = self
+            .connection
+            .as_ref()
+            .ok_or_else(|| "Test the database connection first".to_string())?;
+        utils::loading::store_setting("DBSettings", "endpoint", &settings.endpoint)?;
+        utils::loading::store_setting("DBSettings", "salt", &settings.salt)?;
+        utils::loading::store_secret_setting("DBSettings", "api_key", Some(&settings.api_key))
+    }
+
+    pub fn complete(&mut self) {
+        self.complete = true;
+        self.startup_error = None;
+        self.connection = None;
+    }
+}
+
+/// True while the config file has no database settings. The wizard is only offered then, an
+/// install that is configured but cannot connect reports the error instead.
+pub fn first_run() -> bool {
+    Config::load().is_err_and(|e| e.is_unconfigured())
+}
+
+/// Connects with candidate settings and checks that the tables the app needs can be read.
+pub async fn test_connection(settings: &DatabaseConfig) -> Result<(DbTool, TableReport), String> {
+    let db = DbTool::new(settings)
+        .await
+        .map_err(|e| format!("Failed to connect to the database: {}", e))?;
+    let employees = db
+        .count_rows::<Employee>()
+        .await
+        .map_err(|_| "Cannot read the Employee table".to_string())?;
+    let media = db
+        .count_rows::<Media>()
+        .await
+        .map_err(|_| "Cannot read the Media table".to_string())?;
+    Ok((db, TableReport { employees, media }))
+}
+
+/// Creates the first Dev account. Only allowed while the Employee table is empty, so it
+/// cannot be used to grant access to an existing install.
+pub async fn create_initial_dev(
+    db: &DbTool,
+    id: u16,
+    name: String,
+    password: String,
+) -> Result<(), String> {
+    let existing = db
+        .count_rows::<Employee>()
+        .await
+        .map_err(|_| "Cannot read the Employee table".to_string())?;
+    if existing > 0 {
+        return Err("Employees already exist, log in with an existing account".to_string());
+    }
+    let employee = Employee::new(
+        id,
+        name,
+        String::new(),
+        id,
+        String::new(),
+        String::new(),
+        0,
+        PermissionLevel::Dev,
+        password,
+    )?;
+    db.database_insert(&employee)
+        .await
+        .map_err(|e| format!("Failed to create employee: {}", e))
+}
@@ -2,14 +2,20 @@
 pub mod structs {
 
     use super::super::utils;
-    use crate::types::enums::{MediaType, PermissionLevel};
+    use crate::types::enums::{
+        AuditAction, InterlibraryLoanStatus, MediaType, PermissionLevel, PurchaseStatus, Table,
+        TransferStatus,
+    };
     use serde::{Deserialize, Serialize};
+    use serde_json::Value;
     use std::collections::HashMap;
     use std::fmt;
 
     // Struct Definitions
     // ---------------------------------------------------------------
 
+    /// A stored employee row, secrets included. It never crosses to the frontend and has no
+    /// TypeScript binding, commands hand out `EmployeeInfo` instead.
     #[derive(serde::Deserialize, serde::Serialize, Debug, Default, Clone)]
     pub struct Employee {
         id: u16,
@@ -21,16 +27,379 @@ pub mod structs {
         alloc_budget: u16,
         perm_level: PermissionLevel,
         password: String,
+        #[serde(default)]
+        password_history: Vec<String>,
+        #[serde(default)]
+        reset_code: Option<String>,
+        #[serde(default)]
+        must_change_password: bool,
+        #[serde(default)]
+        totp_secret: Option<String>,
+        #[serde(default)]
+        recovery_codes: Vec<String>,
+        /// The last TOTP time step that was accepted, so a code only works once
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        totp_last_step: Option<u64>,
+        /// 0 for employees that are not assigned to a branch
+        #[serde(default)]
+        branch_id: u16,
+        /// `department` and `project` keep the names for display, older rows only have those
+        #[serde(default)]
+        department_id: Option<u16>,
+        #[serde(default)]
+        project_id: Option<u16>,
     }
 
-    #[derive(serde::Deserialize, serde::Serialize, Debug, Default, Clone)]
+    /// The view of an `Employee` that is safe to hand to the frontend. It never carries
+    /// password hashes, reset codes or TOTP secrets.
+    #[derive(serde::Deserialize, serde::Serialize, ts_rs::TS, Debug, Default, Clone, PartialEq)]
+    #[ts(export_to = "../src/bindings/")]
+    pub struct EmployeeInfo {
+        id: u16,
+        name: String,
+        department: String,
+        boss_id: u16,
+        project: String,
+        subject: String,
+        alloc_budget: u16,
+        perm_level: PermissionLevel,
+        totp_enabled: bool,
+        branch_id: u16,
+        department_id: Option<u16>,
+        project_id: Option<u16>,
+    }
+
+    #[derive(serde::Deserialize, serde::Serialize, ts_rs::TS, Debug, Default, Clone)]
+    #[ts(export_to = "../src/bindings/")]
     pub struct Media {
         id: u16,
         media_type: MediaType,
         name: String,
         borrowable: bool,
+        /// The vendor's name, kept for display. Older rows only have this free-text value.
         vendor: String,
         renter: Option<String>,
+        /// The employee who borrowed the copy. `renter` keeps the name for display, and
+        /// older loans only have that.
+        #[serde(default)]
+        renter_id: Option<u16>,
+        #[serde(default)]
+        #[ts(type = "number | null")]
+        due: Option<u64>,
+        #[serde(default)]
+        vendor_id: Option<u16>,
+        /// The branch holding this copy, 0 for copies that are not assigned to a branch
+        #[serde(default)]
+        branch_id: u16,
+        #[serde(default)]
+        in_transit: bool,
+    }
+
+    #[derive(serde::Deserialize, serde::Serialize, ts_rs::TS, Debug, Default, Clone)]
+    #[ts(export_to = "../src/bindings/")]
+    pub struct Branch {
+        id: u16,
+        name: String,
+        address: String,
+    }
+
+    /// Moves a copy of a media item from one branch to another.
+    #[derive(serde::Deserialize, serde::Serialize, ts_rs::TS, Debug, Default, Clone)]
+    #[ts(export_to = "../src/bindings/")]
+    pub struct Transfer {
+        id: u16,
+        media_id: u16,
+        source_branch: u16,
+        destination_branch: u16,
+        status: TransferStatus,
+        requested_by: u16,
+        #[ts(type = "number")]
+        requested_at: u64,
+        #[ts(type = "number")]
+        updated_at: u64,
+    }
+
+    #[derive(serde::Deserialize, serde::Serialize, ts_rs::TS, Debug, Default, Clone)]
+    #[ts(export_to = "../src/bindings/")]
+    pub struct PartnerLibrary {
+        id: u16,
+        name: String,
+        contact_email: String,
+        address: String,
+    }
+
+    /// A title borrowed from a partner library on behalf of a patron.
+    #[derive(serde::Deserialize, serde::Serialize, ts_rs::TS, Debug, Default, Clone)]
+    #[ts(export_to = "../src/bindings/")]
+    pub struct InterlibraryLoan {
+        id: u16,
+        partner_id: u16,
+        title: String,
+        media_type: MediaType,
+        /// The branch that asked for the item, 0 if the employee has no branch
+        branch_id: u16,
+        requested_by: u16,
+        patron: Option<String>,
+        status: InterlibraryLoanStatus,
+        #[ts(type = "number")]
+        requested_at: u64,
+        #[ts(type = "number")]
+        updated_at: u64,
+        /// When the partner library wants the item back
+        #[ts(type = "number | null")]
+        partner_due: Option<u64>,
+        /// When the patron has to return the item, never later than `partner_due`
+        #[ts(type = "number | null")]
+        patron_due: Option<u64>,
+    }
+
+    /// A title an employee wants bought out of their `alloc_budget`. The approver and the
+    /// requester's department and project are copied in when the request is made, so later
+    /// changes to the employee do not rewrite history.
+    #[derive(serde::Deserialize, serde::Serialize, ts_rs::TS, Debug, Default, Clone)]
+    #[ts(export_to = "../src/bindings/")]
+    pub struct PurchaseRequest {
+        id: u16,
+        requested_by: u16,
+        approver_id: u16,
+        department: String,
+        project: String,
+        #[serde(default)]
+        project_id: Option<u16>,
+        title: String,
+        media_type: MediaType,
+        vendor: String,
+        #[serde(default)]
+        vendor_id: Option<u16>,
+        price: u16,
+        status: PurchaseStatus,
+        #[ts(type = "number")]
+        requested_at: u64,
+        #[ts(type = "number | null")]
+        decided_at: Option<u64>,
+        /// The media record created when the order arrived
+        media_id: Option<u16>,
+    }
+
+    #[derive(serde::Deserialize, serde::Serialize, ts_rs::TS, Debug, Default, Clone)]
+    #[ts(export_to = "../src/bindings/")]
+    pub struct Department {
+        id: u16,
+        name: String,
+    }
+
+    #[derive(serde::Deserialize, serde::Serialize, ts_rs::TS, Debug, Default, Clone)]
+    #[ts(export_to = "../src/bindings/")]
+    pub struct Project {
+        id: u16,
+        name: String,
+        department_id: Option<u16>,
+        /// Seconds since the Unix epoch
+        #[ts(type = "number")]
+        start: u64,
+        #[ts(type = "number | null")]
+        end: Option<u64>,
+        budget: u32,
+    }
+
+    /// How much of a project's budget has gone to approved and received purchases.
+    #[derive(serde::Deserialize, serde::Serialize, ts_rs::TS, Debug, Clone, PartialEq)]
+    #[ts(export_to = "../src/bindings/")]
+    pub struct ProjectSpend {
+        pub project_id: u16,
+        pub name: String,
+        #[ts(type = "number")]
+        pub start: u64,
+        #[ts(type = "number | null")]
+        pub end: Option<u64>,
+        pub budget: u32,
+        pub spent: u32,
+        #[ts(type = "number")]
+        pub remaining: i64,
+    }
+
+    /// Counts of what `App::migrate_departments` created and linked.
+    #[derive(serde::Serialize, ts_rs::TS, Debug, Default, Clone)]
+    #[ts(export_to = "../src/bindings/")]
+    pub struct RegistryMigration {
+        pub departments_created: usize,
+        pub projects_created: usize,
+        pub employees_updated: usize,
+    }
+
+    #[derive(serde::Deserialize, serde::Serialize, ts_rs::TS, Debug, Default, Clone)]
+    #[ts(export_to = "../src/bindings/")]
+    pub struct Vendor {
+        id: u16,
+        name: String,
+        contact_name: String,
+        email: String,
+        phone: String,
+        account_number: String,
+        notes: String,
+    }
+
+    /// The vendors created and the media pointed at them by `Vendor::merge_free_text`.
+    #[derive(serde::Serialize, ts_rs::TS, Debug, Default, Clone)]
+    #[ts(export_to = "../src/bindings/")]
+    pub struct VendorMerge {
+        pub created: Vec<Vendor>,
+        /// (media id, vendor id) pairs
+        pub assignments: Vec<(u16, u16)>,
+    }
+
+    #[derive(serde::Deserialize, serde::Serialize, ts_rs::TS, Debug, Clone, PartialEq)]
+    #[ts(export_to = "../src/bindings/")]
+    pub struct VendorReport {
+        pub vendor_id: u16,
+        pub name: String,
+        pub items: u32,
+        pub purchases: u32,
+        pub spent: u32,
+    }
+
+    #[derive(serde::Deserialize, serde::Serialize, ts_rs::TS, Debug, Clone, PartialEq)]
+    #[ts(export_to = "../src/bindings/")]
+    pub struct BudgetSummary {
+        pub allocated: u16,
+        pub committed: u32,
+        #[ts(type = "number")]
+        pub remaining: i64,
+    }
+
+    /// Spending on approved and received purchases for one department or project.
+    #[derive(serde::Deserialize, serde::Serialize, ts_rs::TS, Debug, Clone, PartialEq)]
+    #[ts(export_to = "../src/bindings/")]
+    pub struct SpendReport {
+        pub name: String,
+        pub purchases: u32,
+        pub spent: u32,
+    }
+
+    #[derive(serde::Serialize, ts_rs::TS, Debug, Clone)]
+    #[ts(export_to = "../src/bindings/")]
+    pub struct OrgNode {
+        pub employee: EmployeeInfo,
+        pub reports: Vec<OrgNode>,
+    }
+
+    /// The reporting tree, along with the `boss_id`s that keep it from being a proper tree.
+    #[derive(serde::Serialize, ts_rs::TS, Debug, Clone)]
+    #[ts(export_to = "../src/bindings/")]
+    pub struct OrgChart {
+        pub roots: Vec<OrgNode>,
+        pub cycles: Vec<Vec<u16>>,
+        pub orphans: Vec<u16>,
+    }
+
+    /// A search hit. Interlibrary loans are listed alongside the library's own media with
+    /// `interlibrary_loan` set to the loan id and a media id of 0, so they cannot be checked
+    /// out like regular media.
+    #[derive(serde::Serialize, ts_rs::TS, Debug, Clone)]
+    #[ts(export_to = "../src/bindings/")]
+    pub struct SearchResult {
+        #[serde(flatten)]
+        media: Media,
+        interlibrary_loan: Option<u16>,
+    }
+
+    #[derive(serde::Deserialize, serde::Serialize, Debug, Default, Clone)]
+    pub struct LoginRecord {
+        id: u16,
+        failed_attempts: u16,
+        last_failure: u64,
+        locked_until: u64,
+    }
+
+    #[derive(serde::Deserialize, serde::Serialize, ts_rs::TS, Debug, Default, Clone)]
+    #[ts(export_to = "../src/bindings/")]
+    pub struct AuditEntry {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[ts(type = "number")]
+        id: Option<u64>,
+        employee_id: Option<u16>,
+        #[ts(type = "number")]
+        timestamp: u64,
+        action: AuditAction,
+        table_name: String,
+        record_id: u16,
+        #[ts(type = "unknown")]
+        changes: Value,
+    }
+
+    #[derive(serde::Deserialize, serde::Serialize, ts_rs::TS, Debug, Default, Clone)]
+    #[ts(export_to = "../src/bindings/")]
+    pub struct AuditFilter {
+        pub employee_id: Option<u16>,
+        pub table_name: Option<String>,
+        #[ts(type = "number | null")]
+        pub from: Option<u64>,
+        #[ts(type = "number | null")]
+        pub to: Option<u64>,
+    }
+
+    #[derive(serde::Serialize, ts_rs::TS, Debug, Clone)]
+    #[ts(export_to = "../src/bindings/")]
+    pub struct TotpEnrolment {
+        pub uri: String,
+        pub secret: String,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct LockoutPolicy {
+        pub max_attempts: u16,
+        pub lockout_secs: u64,
+        pub base_delay_secs: u64,
+    }
+
+    /// The editable shape of a registered table, for building forms.
+    #[derive(serde::Serialize, ts_rs::TS, Debug, Clone, PartialEq)]
+    #[ts(export_to = "../src/bindings/")]
+    pub struct TableSchema {
+        pub table: Table,
+        pub fields: Vec<FieldSchema>,
+    }
+
+    /// One field of a record, as described by `DisplayInfo::schema`.
+    #[derive(serde::Serialize, Debug, Clone, PartialEq)]
+    pub struct FieldSchema {
+        pub name: &'static str,
+        #[serde(flatten)]
+        pub kind: FieldKind,
+        /// The field may be null
+        pub optional: bool,
+        /// Text that may not be left blank
+        pub required: bool,
+        /// Set by the app, e.g. ids and copies of names. Payloads cannot change it.
+        pub read_only: bool,
+        /// The smallest number, or the shortest text
+        pub min: Option<i64>,
+        /// The largest number, or the longest text
+        pub max: Option<i64>,
+    }
+
+    #[derive(serde::Serialize, ts_rs::TS, Debug, Clone, PartialEq)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    #[ts(export_to = "../src/bindings/")]
+    pub enum FieldKind {
+        Text,
+        /// Only ever written, records are handed out without it
+        Password,
+        Integer,
+        Boolean,
+        /// Seconds since the Unix epoch
+        Timestamp,
+        Choice { choices: Vec<Choice> },
+        /// The id of a row in another table, 0 for none where the field is not optional
+        Reference { table: Table },
+    }
+
+    /// A value of an enum field, with its display name.
+    #[derive(serde::Serialize, ts_rs::TS, Debug, Clone, PartialEq)]
+    #[ts(export_to = "../src/bindings/")]
+    pub struct Choice {
+        pub value: String,
+        pub label: String,
     }
 
     // Trait Implementation
@@ -41,6 +410,28 @@ pub mod structs {
         fn get_name(&self) -> &str;
         fn get_table_name() -> &'static str;
         fn additional_setup(&mut self);
+        /// Checks a record before the generic record methods in `App` write it.
+        fn validate(&self) -> Result<(), String> {
+            Ok(())
+        }
+        /// The fields that the generic record methods read and write. Fields left out, such
+        /// as secrets, are never taken from a payload.
+        fn schema() -> Vec<FieldSchema> {
+            Vec::new()
+        }
+        /// Whether writes to the table are recorded in the audit log. Bookkeeping such as
+        /// login counters is not a data change.
+        fn audited() -> bool {
+            true
+        }
+    }
+
+    /// The error for a record that is missing its name.
+    fn require_name(name: &str, kind: &str) -> Result<(), String> {
+        if name.trim().is_empty() {
+            return Err(format!("A {} name is required", kind));
+        }
+        Ok(())
     }
 
     impl DisplayInfo for Employee {
@@ -54,11 +445,38 @@ pub mod structs {
             "Employee"
         }
         fn additional_setup(&mut self) {
+            // New accounts from the generic record methods have no password until they log
+            // in with a reset code, and an empty one must never turn into a valid hash
+            if self.password.is_empty() {
+                return;
+            }
             match utils::security::hash_str(self.password.as_str()) {
                 Ok(hash) => self.password = hash,
                 Err(e) => eprintln!("{}", e),
             }
         }
+        fn validate(&self) -> Result<(), String> {
+            require_name(&self.name, "employee")
+        }
+        fn schema() -> Vec<FieldSchema> {
+            // Passwords and other secrets only change through the password and two-factor
+            // flows, and the names of the department and project follow their ids
+            vec![
+                FieldSchema::integer("id", u16::MAX).read_only(),
+                FieldSchema::text("name").required(),
+                FieldSchema::text("department").read_only(),
+                FieldSchema::reference("boss_id", Table::Employee),
+                FieldSchema::text("project").read_only(),
+                FieldSchema::text("subject"),
+                FieldSchema::integer("alloc_budget", u16::MAX),
+                FieldSchema::choice("perm_level", &PermissionLevel::ALL),
+                FieldSchema::new("password", FieldKind::Password).read_only(),
+                FieldSchema::boolean("must_change_password"),
+                FieldSchema::reference("branch_id", Table::Branch),
+                FieldSchema::reference("department_id", Table::Department).optional(),
+                FieldSchema::reference("project_id", Table::Project).optional(),
+            ]
+        }
     }
 
     impl fmt::Display for Employee {
@@ -74,7 +492,7 @@ pub mod structs {
                 Subject: {}\n\
                 Allocated Budget: {}\n\
                 Permission Level: {}\n\
-                Password: {}",
+                Password: [redacted]",
                 self.id,
                 self.name,
                 self.department,
@@ -82,12 +500,30 @@ pub mod structs {
                 self.project,
                 self.subject,
                 self.alloc_budget,
-                self.perm_level,
-                self.password
+                self.perm_level
             )
         }
     }
 
+    impl From<&Employee> for EmployeeInfo {
+        fn from(emp: &Employee) -> Self {
+            EmployeeInfo {
+                id: emp.id,
+                name: emp.name.clone(),
+                department: emp.department.clone(),
+                boss_id: emp.boss_id,
+                project: emp.project.clone(),
+                subject: emp.subject.clone(),
+                alloc_budget: emp.alloc_budget,
+                perm_level: emp.perm_level.clone(),
+                totp_enabled: emp.totp_secret.is_some(),
+                branch_id: emp.branch_id,
+                department_id: emp.department_id,
+                project_id: emp.project_id,
+            }
+        }
+    }
+
     impl DisplayInfo for Media {
         fn get_id(&self) -> u16 {
             self.id
@@ -101,6 +537,237 @@ pub mod structs {
         fn additional_setup(&mut self) {
             // TODO - Additional setup functionality
         }
+        fn validate(&self) -> Result<(), String> {
+            require_name(&self.name, "media")
+        }
+        fn schema() -> Vec<FieldSchema> {
+            // Renters, due dates and transit change with checkouts and transfers, and the
+            // vendor name follows vendor_id
+            vec![
+                FieldSchema::integer("id", u16::MAX).read_only(),
+                FieldSchema::choice("media_type", &MediaType::ALL),
+                FieldSchema::text("name").required(),
+                FieldSchema::boolean("borrowable"),
+                FieldSchema::text("vendor").read_only(),
+                FieldSchema::text("renter").optional().read_only(),
+                FieldSchema::reference("renter_id", Table::Employee).optional().read_only(),
+                FieldSchema::timestamp("due").optional().read_only(),
+                FieldSchema::reference("vendor_id", Table::Vendor).optional(),
+                FieldSchema::reference("branch_id", Table::Branch),
+                FieldSchema::boolean("in_transit").read_only(),
+            ]
+        }
+    }
+
+    impl DisplayInfo for Branch {
+        fn get_id(&self) -> u16 {
+            self.id
+        }
+        fn get_name(&self) -> &str {
+            self.name.as_str()
+        }
+        fn get_table_name() -> &'static str {
+            "Branch"
+        }
+        fn additional_setup(&mut self) {}
+        fn validate(&self) -> Result<(), String> {
+            require_name(&self.name, "branch")
+        }
+        fn schema() -> Vec<FieldSchema> {
+            vec![
+                FieldSchema::integer("id", u16::MAX).read_only(),
+                FieldSchema::text("name").required(),
+                FieldSchema::text("address"),
+            ]
+        }
+    }
+
+    impl DisplayInfo for Transfer {
+        fn get_id(&self) -> u16 {
+            self.id
+        }
+        fn get_name(&self) -> &str {
+            ""
+        }
+        fn get_table_name() -> &'static str {
+            "Transfer"
+        }
+        fn additional_setup(&mut self) {}
+    }
+
+    impl DisplayInfo for PartnerLibrary {
+        fn get_id(&self) -> u16 {
+            self.id
+        }
+        fn get_name(&self) -> &str {
+            self.name.as_str()
+        }
+        fn get_table_name() -> &'static str {
+            "PartnerLibrary"
+        }
+        fn additional_setup(&mut self) {}
+        fn validate(&self) -> Result<(), String> {
+            require_name(&self.name, "partner library")
+        }
+        fn schema() -> Vec<FieldSchema> {
+            vec![
+                FieldSchema::integer("id", u16::MAX).read_only(),
+                FieldSchema::text("name").required(),
+                FieldSchema::text("contact_email"),
+                FieldSchema::text("address"),
+            ]
+        }
+    }
+
+    impl DisplayInfo for InterlibraryLoan {
+        fn get_id(&self) -> u16 {
+            self.id
+        }
+        fn get_name(&self) -> &str {
+            self.title.as_str()
+        }
+        fn get_table_name() -> &'static str {
+            "InterlibraryLoan"
+        }
+        fn additional_setup(&mut self) {}
+    }
+
+    impl DisplayInfo for PurchaseRequest {
+        fn get_id(&self) -> u16 {
+            self.id
+        }
+        fn get_name(&self) -> &str {
+            self.title.as_str()
+        }
+        fn get_table_name() -> &'static str {
+            "PurchaseRequest"
+        }
+        fn additional_setup(&mut self) {}
+    }
+
+    impl DisplayInfo for Department {
+        fn get_id(&self) -> u16 {
+            self.id
+        }
+        fn get_name(&self) -> &str {
+            self.name.as_str()
+        }
+        fn get_table_name() -> &'static str {
+            "Department"
+        }
+        fn additional_setup(&mut self) {}
+        fn validate(&self) -> Result<(), String> {
+            require_name(&self.name, "department")
+        }
+        fn schema() -> Vec<FieldSchema> {
+            vec![
+                FieldSchema::integer("id", u16::MAX).read_only(),
+                FieldSchema::text("name").required(),
+            ]
+        }
+    }
+
+    impl DisplayInfo for Project {
+        fn get_id(&self) -> u16 {
+            self.id
+        }
+        fn get_name(&self) -> &str {
+            self.name.as_str()
+        }
+        fn get_table_name() -> &'static str {
+            "Project"
+        }
+        fn additional_setup(&mut self) {}
+        fn validate(&self) -> Result<(), String> {
+            require_name(&self.name, "project")?;
+            if self.end.is_some_and(|end| end < self.start) {
+                return Err("A project cannot end before it starts".to_string());
+            }
+            Ok(())
+        }
+        fn schema() -> Vec<FieldSchema> {
+            vec![
+                FieldSchema::integer("id", u16::MAX).read_only(),
+                FieldSchema::text("name").required(),
+                FieldSchema::reference("department_id", Table::Department).optional(),
+                FieldSchema::timestamp("start"),
+                FieldSchema::timestamp("end").optional(),
+                FieldSchema::integer("budget", u32::MAX),
+            ]
+        }
+    }
+
+    impl DisplayInfo for Vendor {
+        fn get_id(&self) -> u16 {
+            self.id
+        }
+        fn get_name(&self) -> &str {
+            self.name.as_str()
+        }
+        fn get_table_name() -> &'static str {
+            "Vendor"
+        }
+        fn additional_setup(&mut self) {}
+        fn validate(&self) -> Result<(), String> {
+            require_name(&self.name, "vendor")
+        }
+        fn schema() -> Vec<FieldSchema> {
+            vec![
+                FieldSchema::integer("id", u16::MAX).read_only(),
+                FieldSchema::text("name").required(),
+                FieldSchema::text("contact_name"),
+                FieldSchema::text("email"),
+                FieldSchema::text("phone"),
+                FieldSchema::text("account_number"),
+                FieldSchema::text("notes"),
+            ]
+        }
+    }
+
+    impl From<Media> for SearchResult {
+        fn from(media: Media) -> Self {
+            SearchResult {
+                media,
+                interlibrary_loan: None,
+            }
+        }
+    }
+
+    impl From<&InterlibraryLoan> for SearchResult {
+        fn from(loan: &InterlibraryLoan) -> Self {
+            SearchResult {
+                media: Media {
+                    id: 0,
+                    media_type: loan.media_type.clone(),
+                    name: loan.title.clone(),
+                    borrowable: false,
+                    vendor: String::new(),
+                    renter: loan.patron.clone(),
+                    renter_id: None,
+                    due: loan.patron_due,
+                    vendor_id: None,
+                    branch_id: loan.branch_id,
+                    in_transit: false,
+                },
+                interlibrary_loan: Some(loan.id),
+            }
+        }
+    }
+
+    impl DisplayInfo for LoginRecord {
+        fn get_id(&self) -> u16 {
+            self.id
+        }
+        fn get_name(&self) -> &str {
+            ""
+        }
+        fn get_table_name() -> &'static str {
+            "LoginRecord"
+        }
+        fn additional_setup(&mut self) {}
+        fn audited() -> bool {
+            false
+        }
     }
 
     impl fmt::Display for Media {
@@ -142,9 +809,7 @@ pub mod structs {
             perm_level: PermissionLevel,
             password: String,
         ) -> Result<Self, &'static str> {
-            if password.len() < 8 {
-                return Err("Password should be at least 8 characters long.");
-            }
+            Employee::validate_password(&password)?;
             match utils::security::hash_str(password.as_str()) {
                 Ok(hash) => Ok(Employee {
                     id,
@@ -156,10 +821,105 @@ pub mod structs {
                     alloc_budget,
                     perm_level,
                     password: hash,
+                    ..Default::default()
                 }),
                 Err(_) => Err("Error occurred while hashing password"),
             }
         }
+        pub fn validate_password(password: &str) -> Result<(), &'static str> {
+            if password.len() < 8 {
+                return Err("Password should be at least 8 characters long.");
+            }
+            Ok(())
+        }
+
+        /// Replaces the password, refusing the current one and the last `history_len` others.
+        pub fn change_password(
+            &mut self,
+            password: &str,
+            history_len: usize,
+        ) -> Result<(), &'static str> {
+            Employee::validate_password(password)?;
+            let reused = std::iter::once(&self.password)
+                .chain(self.password_history.iter().take(history_len))
+                .any(|hash| utils::security::verify_password(hash, password).unwrap_or(false));
+            if reused {
+                return Err("Password was used recently, please choose another.");
+            }
+            let hash = utils::security::hash_str(password)
+                .map_err(|_| "Error occurred while hashing password")?;
+
+            let old = std::mem::replace(&mut self.password, hash);
+            self.password_history.insert(0, old);
+            self.password_history.truncate(history_len);
+            self.reset_code = None;
+            self.must_change_password = false;
+            Ok(())
+        }
+
+        /// Issues a one-time code that can be used to log in once, after which the password
+        /// has to be changed. Only a hash of the code is kept.
+        pub fn issue_reset_code(&mut self) -> Result<String, &'static str> {
+            let code = utils::security::generate_code(12);
+            let hash = utils::security::hash_str(&code)
+                .map_err(|_| "Error occurred while hashing reset code")?;
+            self.reset_code = Some(hash);
+            self.must_change_password = true;
+            Ok(code)
+        }
+
+        /// Checks `code` against the outstanding reset code and consumes it on a match.
+        pub fn redeem_reset_code(&mut self, code: &str) -> bool {
+            let redeemed = self
+                .reset_code
+                .as_deref()
+                .is_some_and(|hash| utils::security::verify_password(hash, code).unwrap_or(false));
+            if redeemed {
+                self.reset_code = None;
+            }
+            redeemed
+        }
+        pub fn must_change_password(&self) -> bool {
+            self.must_change_password
+        }
+        pub fn totp_secret(&self) -> Option<&str> {
+            self.totp_secret.as_deref()
+        }
+
+        /// Changes whenever the password, permission level or two-factor secret do, so a
+        /// sign-in that was checked against the old ones can be told apart.
+        pub fn session_stamp(&self) -> String {
+            utils::crypto::sha256_hex(&format!(
+                "{}|{:?}|{}",
+                self.password,
+                self.perm_level,
+                self.totp_secret.as_deref().unwrap_or_default()
+            ))
+        }
+
+        pub fn totp_last_step(&self) -> Option<u64> {
+            self.totp_last_step
+        }
+        pub fn set_totp_last_step(&mut self, step: u64) {
+            self.totp_last_step = Some(step);
+        }
+
+        /// Stores an encrypted TOTP secret together with the hashes of its recovery codes.
+        pub fn set_totp(&mut self, encrypted_secret: String, recovery_codes: &[String]) {
+            self.totp_secret = Some(encrypted_secret);
+            self.recovery_codes = recovery_codes
+                .iter()
+                .map(|code| utils::crypto::sha256_hex(code))
+                .collect();
+        }
+
+        /// Checks `code` against the unused recovery codes and consumes it on a match.
+        pub fn redeem_recovery_code(&mut self, code: &str) -> bool {
+            let hash = utils::crypto::sha256_hex(code.trim());
+            let before = self.recovery_codes.len();
+            self.recovery_codes.retain(|stored| *stored != hash);
+            self.recovery_codes.len() != before
+        }
         pub fn department(&self) -> &str {
             &self.department
         }
@@ -178,6 +938,28 @@ pub mod structs {
         pub fn perm_level(&self) -> &PermissionLevel {
             &self.perm_level
         }
+        pub fn branch_id(&self) -> u16 {
+            self.branch_id
+        }
+        pub fn set_branch_id(&mut self, branch_id: u16) {
+            self.branch_id = branch_id;
+        }
+        pub fn department_id(&self) -> Option<u16> {
+            self.department_id
+        }
+        pub fn project_id(&self) -> Option<u16> {
+            self.project_id
+        }
+        /// Points the employee at a department record, copying its name for display.
+        pub fn assign_department(&mut self, department: Option<&Department>) {
+            self.department_id = department.map(|department| department.id);
+            self.department = department.map_or_else(String::new, |d| d.name.clone());
+        }
+        /// Points the employee at a project record, copying its name for display.
+        pub fn assign_project(&mut self, project: Option<&Project>) {
+            self.project_id = project.map(|project| project.id);
+            self.project = project.map_or_else(String::new, |p| p.name.clone());
+        }
         pub fn password(&self) -> &str {
             &self.password
         }
@@ -229,37 +1011,640 @@ pub mod structs {
                 borrowable,
                 vendor,
                 renter: Some(renter),
+                renter_id: None,
+                due: None,
+                vendor_id: None,
+                branch_id: 0,
+                in_transit: false,
+            }
+        }
+        pub fn media_type(&self) -> &MediaType {
+            &self.media_type
+        }
+        pub fn borrowable(&self) -> bool {
+            self.borrowable
+        }
+        pub fn vendor(&self) -> &str {
+            &self.vendor
+        }
+        pub fn renter(&self) -> Option<&str> {
+            self.renter.as_deref()
+        }
+        pub fn set_id(&mut self, id: u16) {
+            self.id = id;
+        }
+        pub fn set_media_type(&mut self, media_type: MediaType) {
+            self.media_type = media_type;
+        }
+        pub fn set_name(&mut self, name: String) {
+            self.name = name;
+        }
+        pub fn set_borrowable(&mut self, borrowable: bool) {
+            self.borrowable = borrowable;
+        }
+        pub fn set_vendor(&mut self, vendor: String) {
+            self.vendor = vendor;
+        }
+        pub fn vendor_id(&self) -> Option<u16> {
+            self.vendor_id
+        }
+        /// Points the media at a vendor record, copying its name for display.
+        pub fn assign_vendor(&mut self, vendor: &Vendor) {
+            self.vendor_id = Some(vendor.id);
+            self.vendor = vendor.name.clone();
+        }
+        pub fn renter_id(&self) -> Option<u16> {
+            self.renter_id
+        }
+        /// Lends the copy to an employee, keeping their name for display.
+        pub fn set_renter(&mut self, employee: &Employee) {
+            self.renter = Some(employee.name.clone());
+            self.renter_id = Some(employee.id);
+        }
+        pub fn clear_renter(&mut self) {
+            self.renter = None;
+            self.renter_id = None;
+            self.due = None;
+        }
+        pub fn due(&self) -> Option<u64> {
+            self.due
+        }
+        /// Sets when the current loan has to be returned, in seconds since the Unix epoch.
+        pub fn set_due(&mut self, due: u64) {
+            self.due = Some(due);
+        }
+        pub fn is_available(&self) -> bool {
+            self.borrowable && !self.in_transit && self.renter.as_deref().is_none_or(str::is_empty)
+        }
+        pub fn branch_id(&self) -> u16 {
+            self.branch_id
+        }
+        pub fn set_branch_id(&mut self, branch_id: u16) {
+            self.branch_id = branch_id;
+        }
+        pub fn in_transit(&self) -> bool {
+            self.in_transit
+        }
+        /// Whether the copy is held by `branch`. Unassigned copies belong to every branch.
+        pub fn in_branch(&self, branch: u16) -> bool {
+            self.branch_id == 0 || self.branch_id == branch
+        }
+    }
+
+    impl Branch {
+        pub fn new(id: u16, name: String, address: String) -> Branch {
+            Branch { id, name, address }
+        }
+        pub fn address(&self) -> &str {
+            &self.address
+        }
+    }
+
+    impl Transfer {
+        pub fn new(
+            id: u16,
+            media: &Media,
+            destination_branch: u16,
+            requested_by: u16,
+            now: u64,
+        ) -> Transfer {
+            Transfer {
+                id,
+                media_id: media.id,
+                source_branch: media.branch_id,
+                destination_branch,
+                status: TransferStatus::Requested,
+                requested_by,
+                requested_at: now,
+                updated_at: now,
+            }
+        }
+        pub fn media_id(&self) -> u16 {
+            self.media_id
+        }
+        pub fn source_branch(&self) -> u16 {
+            self.source_branch
+        }
+        pub fn destination_branch(&self) -> u16 {
+            self.destination_branch
+        }
+        pub fn status(&self) -> &TransferStatus {
+            &self.status
+        }
+        pub fn involves(&self, branch: u16) -> bool {
+            self.source_branch == branch || self.destination_branch == branch
+        }
+        /// Whether the copy is still waiting to be sent or on its way.
+        pub fn is_open(&self) -> bool {
+            self.status != TransferStatus::Received
+        }
+
+        /// Moves the transfer to its next status and updates the location of the copy to
+        /// match: it is in transit once dispatched and held by the new branch once received.
+        pub fn advance(&mut self, media: &mut Media, now: u64) -> Result<(), String> {
+            if media.id != self.media_id {
+                return Err("Transfer is for a different item".to_string());
+            }
+            match self.status {
+                TransferStatus::Requested => {
+                    if !media.is_available() {
+                        return Err(format!("{} is not available", media.name));
+                    }
+                    media.in_transit = true;
+                    self.status = TransferStatus::InTransit;
+                }
+                TransferStatus::InTransit => {
+                    media.in_transit = false;
+                    media.branch_id = self.destination_branch;
+                    self.status = TransferStatus::Received;
+                }
+                TransferStatus::Received => {
+                    return Err("Transfer has already been received".to_string())
+                }
+            }
+            self.updated_at = now;
+            Ok(())
+        }
+    }
+
+    impl PartnerLibrary {
+        pub fn new(id: u16, name: String, contact_email: String, address: String) -> Self {
+            PartnerLibrary {
+                id,
+                name,
+                contact_email,
+                address,
+            }
+        }
+        pub fn contact_email(&self) -> &str {
+            &self.contact_email
+        }
+        pub fn address(&self) -> &str {
+            &self.address
+        }
+    }
+
+    impl InterlibraryLoan {
+        pub fn new(
+            id: u16,
+            partner_id: u16,
+            title: String,
+            media_type: MediaType,
+            requested_by: &Employee,
+            patron: Option<String>,
+            now: u64,
+        ) -> Self {
+            InterlibraryLoan {
+                id,
+                partner_id,
+                title,
+                media_type,
+                branch_id: requested_by.branch_id,
+                requested_by: requested_by.id,
+                patron,
+                status: InterlibraryLoanStatus::Requested,
+                requested_at: now,
+                updated_at: now,
+                partner_due: None,
+                patron_due: None,
+            }
+        }
+        pub fn partner_id(&self) -> u16 {
+            self.partner_id
+        }
+        pub fn in_branch(&self, branch: u16) -> bool {
+            self.branch_id == 0 || self.branch_id == branch
+        }
+        pub fn patron(&self) -> Option<&str> {
+            self.patron.as_deref()
+        }
+        pub fn status(&self) -> &InterlibraryLoanStatus {
+            &self.status
+        }
+        pub fn partner_due(&self) -> Option<u64> {
+            self.partner_due
+        }
+        pub fn patron_due(&self) -> Option<u64> {
+            self.patron_due
+        }
+
+        /// Whether the item is physically with the library, from its arrival until it is
+        /// shipped back, excluding the time it is lent out.
+        pub fn is_on_site(&self) -> bool {
+            matches!(
+                self.status,
+                InterlibraryLoanStatus::Received | InterlibraryLoanStatus::Returned
+            )
+        }
+
+        /// Moves the loan to its next status. Receiving the item needs the partner's due date
+        /// and lending it needs a patron, unless one was named in the request. Patrons get at
+        /// most `loan_secs` and never more than the partner allows.
+        pub fn advance(
+            &mut self,
+            partner_due: Option<u64>,
+            patron: Option<String>,
+            loan_secs: u64,
+            now: u64,
+        ) -> Result<(), String> {
+            self.status = match self.status {
+                InterlibraryLoanStatus::Requested => InterlibraryLoanStatus::SentToPartner,
+                InterlibraryLoanStatus::SentToPartner => {
+                    let due = partner_due
+                        .ok_or_else(|| "The partner's due date is required".to_string())?;
+                    if due <= now {
+                        return Err("The partner's due date has already passed".to_string());
+                    }
+                    self.partner_due = Some(due);
+                    InterlibraryLoanStatus::Received
+                }
+                InterlibraryLoanStatus::Received => {
+                    let patron = patron
+                        .filter(|patron| !patron.is_empty())
+                        .or_else(|| self.patron.clone())
+                        .ok_or_else(|| "A patron is required".to_string())?;
+                    let latest = self.partner_due.unwrap_or(u64::MAX);
+                    self.patron = Some(patron);
+                    self.patron_due = Some(now.saturating_add(loan_secs).min(latest));
+                    InterlibraryLoanStatus::OnLoan
+                }
+                InterlibraryLoanStatus::OnLoan => {
+                    self.patron_due = None;
+                    InterlibraryLoanStatus::Returned
+                }
+                InterlibraryLoanStatus::Returned => InterlibraryLoanStatus::ShippedBack,
+                InterlibraryLoanStatus::ShippedBack => {
+                    return Err("Loan has already been shipped back".to_string())
+                }
+            };
+            self.updated_at = now;
+            Ok(())
+        }
+    }
+
+    impl PurchaseRequest {
+        pub fn new(
+            id: u16,
+            requested_by: &Employee,
+            title: String,
+            media_type: MediaType,
+            vendor: &Vendor,
+            price: u16,
+            now: u64,
+        ) -> Self {
+            PurchaseRequest {
+                id,
+                requested_by: requested_by.id,
+                approver_id: requested_by.boss_id,
+                department: requested_by.department.clone(),
+                project: requested_by.project.clone(),
+                project_id: requested_by.project_id,
+                title,
+                media_type,
+                vendor: vendor.name.clone(),
+                vendor_id: Some(vendor.id),
+                price,
+                status: PurchaseStatus::Pending,
+                requested_at: now,
+                decided_at: None,
+                media_id: None,
+            }
+        }
+        pub fn requested_by(&self) -> u16 {
+            self.requested_by
+        }
+        pub fn approver_id(&self) -> u16 {
+            self.approver_id
+        }
+        pub fn price(&self) -> u16 {
+            self.price
+        }
+        pub fn status(&self) -> &PurchaseStatus {
+            &self.status
+        }
+        pub fn media_id(&self) -> Option<u16> {
+            self.media_id
+        }
+
+        /// Whether the price counts against the requester's budget.
+        pub fn is_committed(&self) -> bool {
+            matches!(self.status, PurchaseStatus::Approved | PurchaseStatus::Received)
+        }
+
+        pub fn decide(&mut self, approve: bool, now: u64) -> Result<(), String> {
+            if self.status != PurchaseStatus::Pending {
+                return Err("Purchase request has already been decided".to_string());
+            }
+            self.status = if approve {
+                PurchaseStatus::Approved
+            } else {
+                PurchaseStatus::Rejected
+            };
+            self.decided_at = Some(now);
+            Ok(())
+        }
+
+        /// Marks an approved order as arrived and returns the media record for it.
+        pub fn receive(&mut self, media_id: u16, branch_id: u16) -> Result<Media, String> {
+            if self.status != PurchaseStatus::Approved {
+                return Err("Only approved purchases can be received".to_string());
+            }
+            self.status = PurchaseStatus::Received;
+            self.media_id = Some(media_id);
+            Ok(Media {
+                id: media_id,
+                media_type: self.media_type.clone(),
+                name: self.title.clone(),
+                borrowable: true,
+                vendor: self.vendor.clone(),
+                renter: None,
+                renter_id: None,
+                due: None,
+                vendor_id: self.vendor_id,
+                branch_id,
+                in_transit: false,
+            })
+        }
+
+        /// Works out how much of `employee`'s budget is left after their committed purchases.
+        pub fn budget(employee: &Employee, requests: &[PurchaseRequest]) -> BudgetSummary {
+            let committed = requests
+                .iter()
+                .filter(|request| request.requested_by == employee.id && request.is_committed())
+                .map(|request| request.price as u32)
+                .sum();
+            BudgetSummary {
+                allocated: employee.alloc_budget,
+                committed,
+                remaining: employee.alloc_budget as i64 - committed as i64,
+            }
+        }
+
+        /// Totals committed spending per department, or per project with `by_project`.
+        pub fn spend_report(requests: &[PurchaseRequest], by_project: bool) -> Vec<SpendReport> {
+            let mut totals: HashMap<&str, SpendReport> = HashMap::new();
+            for request in requests.iter().filter(|request| request.is_committed()) {
+                let name = if by_project {
+                    &request.project
+                } else {
+                    &request.department
+                };
+                let entry = totals.entry(name).or_insert_with(|| SpendReport {
+                    name: name.clone(),
+                    purchases: 0,
+                    spent: 0,
+                });
+                entry.purchases += 1;
+                entry.spent += request.price as u32;
+            }
+            let mut report: Vec<SpendReport> = totals.into_values().collect();
+            report.sort_by(|a, b| a.name.cmp(&b.name));
+            report
+        }
+    }
+
+    impl Department {
+        pub fn new(id: u16, name: String) -> Self {
+            Department { id, name }
+        }
+    }
+
+    impl Project {
+        pub fn new(id: u16, name: String, start: u64, end: Option<u64>, budget: u32) -> Self {
+            Project {
+                id,
+                name,
+                department_id: None,
+                start,
+                end,
+                budget,
+            }
+        }
+        pub fn department_id(&self) -> Option<u16> {
+            self.department_id
+        }
+        pub fn start(&self) -> u64 {
+            self.start
+        }
+        pub fn end(&self) -> Option<u64> {
+            self.end
+        }
+        pub fn budget(&self) -> u32 {
+            self.budget
+        }
+
+        /// Totals the approved and received purchases charged to each project.
+        pub fn spending(projects: &[Project], requests: &[PurchaseRequest]) -> Vec<ProjectSpend> {
+            let mut report: Vec<ProjectSpend> = projects
+                .iter()
+                .map(|project| {
+                    let spent: u32 = requests
+                        .iter()
+                        .filter(|request| {
+                            request.project_id == Some(project.id) && request.is_committed()
+                        })
+                        .map(|request| request.price as u32)
+                        .sum();
+                    ProjectSpend {
+                        project_id: project.id,
+                        name: project.name.clone(),
+                        start: project.start,
+                        end: project.end,
+                        budget: project.budget,
+                        spent,
+                        remaining: project.budget as i64 - spent as i64,
+                    }
+                })
+                .collect();
+            report.sort_by(|a, b| a.name.cmp(&b.name));
+            report
+        }
+    }
+
+    impl Vendor {
+        pub fn new(id: u16, name: String) -> Self {
+            Vendor {
+                id,
+                name,
+                ..Default::default()
+            }
+        }
+        pub fn email(&self) -> &str {
+            &self.email
+        }
+        pub fn account_number(&self) -> &str {
+            &self.account_number
+        }
+
+        /// Reduces a vendor name to what identifies it, so that "Sega", "SEGA" and
+        /// "Sega Corp." all compare equal.
+        pub fn normalize_name(name: &str) -> String {
+            const SUFFIXES: [&str; 8] =
+                ["co", "company", "corp", "corporation", "inc", "incorporated", "llc", "ltd"];
+            let cleaned: String = name
+                .to_lowercase()
+                .chars()
+                .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+                .collect();
+            let mut words: Vec<&str> = cleaned.split_whitespace().collect();
+            while words.len() > 1 && words.last().is_some_and(|word| SUFFIXES.contains(word)) {
+                words.pop();
+            }
+            words.join(" ")
+        }
+
+        /// Works out how to point media that only have a free-text vendor at vendor records.
+        /// Names are matched after `normalize_name`, see `utils::migrate::merge_names`.
+        pub fn merge_free_text(vendors: &[Vendor], media: &[Media], next_id: u16) -> VendorMerge {
+            let existing: Vec<(u16, &str)> = vendors
+                .iter()
+                .map(|vendor| (vendor.id, vendor.name.as_str()))
+                .collect();
+            let values: Vec<(u16, &str)> = media
+                .iter()
+                .filter(|item| item.vendor_id.is_none())
+                .map(|item| (item.id, item.vendor.as_str()))
+                .collect();
+            let merge =
+                utils::migrate::merge_names(&existing, &values, next_id, Vendor::normalize_name);
+            VendorMerge {
+                created: merge
+                    .created
+                    .into_iter()
+                    .map(|(id, name)| Vendor::new(id, name))
+                    .collect(),
+                assignments: merge.assignments,
+            }
+        }
+
+        /// Counts the items from each vendor and totals what was spent with them on approved
+        /// and received purchases.
+        pub fn report(
+            vendors: &[Vendor],
+            media: &[Media],
+            requests: &[PurchaseRequest],
+        ) -> Vec<VendorReport> {
+            let mut report: Vec<VendorReport> = vendors
+                .iter()
+                .map(|vendor| VendorReport {
+                    vendor_id: vendor.id,
+                    name: vendor.name.clone(),
+                    items: 0,
+                    purchases: 0,
+                    spent: 0,
+                })
+                .collect();
+            report.sort_by(|a, b| a.name.cmp(&b.name));
+            let find = |report: &[VendorReport], id: Option<u16>| {
+                id.and_then(|id| report.iter().position(|line| line.vendor_id == id))
+            };
+            for item in media {
+                if let Some(pos) = find(&report, item.vendor_id) {
+                    report[pos].items += 1;
+                }
+            }
+            for request in requests.iter().filter(|request| request.is_committed()) {
+                if let Some(pos) = find(&report, request.vendor_id) {
+                    report[pos].purchases += 1;
+                    report[pos].spent += request.price as u32;
+                }
+            }
+            report
+        }
+    }
+
+    impl LoginRecord {
+        pub fn new(id: u16) -> LoginRecord {
+            LoginRecord {
+                id,
+                ..Default::default()
             }
         }
-        pub fn media_type(&self) -> &MediaType {
-            &self.media_type
+        pub fn failed_attempts(&self) -> u16 {
+            self.failed_attempts
         }
-        pub fn borrowable(&self) -> bool {
-            self.borrowable
+
+        /// Returns how many seconds must pass before another attempt is allowed, if any.
+        /// Each failure doubles the delay until the account is locked outright.
+        pub fn retry_after(&self, policy: &LockoutPolicy, now: u64) -> Option<u64> {
+            if self.locked_until > now {
+                return Some(self.locked_until - now);
+            }
+            if self.failed_attempts == 0 {
+                return None;
+            }
+            let delay = policy
+                .base_delay_secs
+                .saturating_mul(1 << (self.failed_attempts - 1).min(16))
+                .min(policy.lockout_secs);
+            let next_attempt = self.last_failure.saturating_add(delay);
+            (next_attempt > now).then(|| next_attempt - now)
         }
-        pub fn vendor(&self) -> &str {
-            &self.vendor
+
+        pub fn register_failure(&mut self, policy: &LockoutPolicy, now: u64) {
+            self.failed_attempts += 1;
+            self.last_failure = now;
+            if self.failed_attempts >= policy.max_attempts {
+                self.failed_attempts = 0;
+                self.locked_until = now.saturating_add(policy.lockout_secs);
+            }
         }
-        pub fn renter(&self) -> Option<&str> {
-            self.renter.as_deref()
+
+        pub fn reset(&mut self) {
+            self.failed_attempts = 0;
+            self.locked_until = 0;
         }
-        pub fn set_id(&mut self, id: u16) {
-            self.id = id;
+
+        pub fn is_locked(&self, now: u64) -> bool {
+            self.locked_until > now
         }
-        pub fn set_media_type(&mut self, media_type: MediaType) {
-            self.media_type = media_type;
+    }
+
+    impl AuditEntry {
+        pub fn new(
+            employee_id: Option<u16>,
+            action: AuditAction,
+            table_name: &str,
+            record_id: u16,
+            changes: Value,
+        ) -> AuditEntry {
+            AuditEntry {
+                id: None,
+                employee_id,
+                timestamp: utils::time::now(),
+                action,
+                table_name: table_name.to_string(),
+                record_id,
+                changes,
+            }
         }
-        pub fn set_name(&mut self, name: String) {
-            self.name = name;
+        pub fn get_table_name() -> &'static str {
+            "AuditLog"
         }
-        pub fn set_borrowable(&mut self, borrowable: bool) {
-            self.borrowable = borrowable;
+        pub fn employee_id(&self) -> Option<u16> {
+            self.employee_id
         }
-        pub fn set_vendor(&mut self, vendor: String) {
-            self.vendor = vendor;
+        pub fn timestamp(&self) -> u64 {
+            self.timestamp
+        }
+        pub fn action(&self) -> &AuditAction {
+            &self.action
         }
-        pub fn set_renter(&mut self, renter: String) {
-            self.renter = Some(renter);
+        pub fn record_id(&self) -> u16 {
+            self.record_id
+        }
+        pub fn changes(&self) -> &Value {
+            &self.changes
+        }
+    }
+
+    impl Default for LockoutPolicy {
+        fn default() -> Self {
+            LockoutPolicy {
+                max_attempts: 5,
+                lockout_secs: 900,
+                base_delay_secs: 1,
+            }
         }
     }
 
@@ -355,13 +1740,192 @@ pub mod structs {
             }
         }
     }
+
+    impl FieldSchema {
+        pub fn new(name: &'static str, kind: FieldKind) -> FieldSchema {
+            FieldSchema {
+                name,
+                kind,
+                optional: false,
+                required: false,
+                read_only: false,
+                min: None,
+                max: None,
+            }
+        }
+        pub fn text(name: &'static str) -> FieldSchema {
+            FieldSchema::new(name, FieldKind::Text)
+        }
+        pub fn integer(name: &'static str, max: impl Into<i64>) -> FieldSchema {
+            FieldSchema::new(name, FieldKind::Integer).min(0).max(max)
+        }
+        pub fn boolean(name: &'static str) -> FieldSchema {
+            FieldSchema::new(name, FieldKind::Boolean)
+        }
+        pub fn timestamp(name: &'static str) -> FieldSchema {
+            FieldSchema::new(name, FieldKind::Timestamp).min(0)
+        }
+        /// A field holding one of `values`, an enum such as `MediaType`.
+        pub fn choice<T>(name: &'static str, values: &[T]) -> FieldSchema
+        where
+            T: Serialize + fmt::Display,
+        {
+            let choices = values
+                .iter()
+                .map(|value| Choice {
+                    value: serde_json::to_value(value)
+                        .ok()
+                        .and_then(|value| value.as_str().map(str::to_string))
+                        .unwrap_or_default(),
+                    label: value.to_string(),
+                })
+                .collect();
+            FieldSchema::new(name, FieldKind::Choice { choices })
+        }
+        pub fn reference(name: &'static str, table: Table) -> FieldSchema {
+            FieldSchema::new(name, FieldKind::Reference { table }).min(0).max(u16::MAX)
+        }
+        pub fn optional(mut self) -> FieldSchema {
+            self.optional = true;
+            self
+        }
+        pub fn required(mut self) -> FieldSchema {
+            self.required = true;
+            self
+        }
+        pub fn read_only(mut self) -> FieldSchema {
+            self.read_only = true;
+            self
+        }
+        pub fn min(mut self, min: impl Into<i64>) -> FieldSchema {
+            self.min = Some(min.into());
+            self
+        }
+        pub fn max(mut self, max: impl Into<i64>) -> FieldSchema {
+            self.max = Some(max.into());
+            self
+        }
+
+        /// Checks a value against the field's rules. Whether it has the right type is checked
+        /// when the record is deserialized.
+        pub fn check(&self, value: &Value) -> Result<(), String> {
+            let name = self.name;
+            match (&self.kind, value) {
+                (_, Value::Null) if !self.optional => Err(format!("{} is required", name)),
+                (FieldKind::Choice { choices }, Value::String(value)) => {
+                    if choices.iter().any(|choice| choice.value == *value) {
+                        Ok(())
+                    } else {
+                        Err(format!("{} must be one of {}", name, self.choice_list()))
+                    }
+                }
+                (FieldKind::Text | FieldKind::Password, Value::String(text)) => {
+                    let length = text.chars().count() as i64;
+                    if self.required && text.trim().is_empty() {
+                        Err(format!("{} is required", name))
+                    } else if self.min.is_some_and(|min| length < min) {
+                        Err(format!("{} must be at least {} characters", name, self.min.unwrap()))
+                    } else if self.max.is_some_and(|max| length > max) {
+                        Err(format!("{} must be at most {} characters", name, self.max.unwrap()))
+                    } else {
+                        Ok(())
+                    }
+                }
+                (_, Value::Number(number)) => {
+                    let in_range = number.as_i64().is_some_and(|number| {
+                        self.min.is_none_or(|min| number >= min)
+                            && self.max.is_none_or(|max| number <= max)
+                    });
+                    if in_range {
+                        Ok(())
+                    } else {
+                        Err(format!(
+                            "{} must be between {} and {}",
+                            name,
+                            self.min.unwrap_or(i64::MIN),
+                            self.max.unwrap_or(i64::MAX)
+                        ))
+                    }
+                }
+                _ => Ok(()),
+            }
+        }
+
+        /// Reads a value typed at a prompt or given as `field=value`. Choices match by value
+        /// or display name, and optional fields take `null` or nothing.
+        pub fn parse(&self, input: &str) -> Result<Value, String> {
+            let input = input.trim();
+            if self.optional && (input.is_empty() || input == "null") {
+                return Ok(Value::Null);
+            }
+            let sample = match &self.kind {
+                FieldKind::Choice { choices } => {
+                    return choices
+                        .iter()
+                        .find(|choice| {
+                            choice.value.eq_ignore_ascii_case(input)
+                                || choice.label.eq_ignore_ascii_case(input)
+                        })
+                        .map(|choice| Value::String(choice.value.clone()))
+                        .ok_or_else(|| format!("Expected one of {}", self.choice_list()));
+                }
+                FieldKind::Text | FieldKind::Password => Value::String(String::new()),
+                FieldKind::Boolean => Value::Bool(false),
+                FieldKind::Integer | FieldKind::Timestamp | FieldKind::Reference { .. } => {
+                    Value::from(0)
+                }
+            };
+            utils::user::parse_value(&sample, input)
+        }
+
+        fn choice_list(&self) -> String {
+            match &self.kind {
+                FieldKind::Choice { choices } => choices
+                    .iter()
+                    .map(|choice| choice.value.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                _ => String::new(),
+            }
+        }
+    }
+
+    /// Written by hand because ts-rs cannot flatten an enum. Serde puts the `FieldKind` tag
+    /// and its fields next to the others, which TypeScript spells as an intersection.
+    impl ts_rs::TS for FieldSchema {
+        const EXPORT_TO: Option<&'static str> = Some("../src/bindings/FieldSchema.ts");
+
+        fn name() -> String {
+            "FieldSchema".to_string()
+        }
+        fn decl() -> String {
+            format!("type FieldSchema = {};", Self::inline())
+        }
+        fn inline() -> String {
+            "{ name: string, optional: boolean, required: boolean, read_only: boolean, \
+             min: number | null, max: number | null, } & FieldKind"
+                .to_string()
+        }
+        fn dependencies() -> Vec<ts_rs::Dependency> {
+            ts_rs::Dependency::from_ty::<FieldKind>().into_iter().collect()
+        }
+        fn transparent() -> bool {
+            false
+        }
+    }
 }
 
 pub mod enums {
+    use crate::types::structs::{
+        Branch, Department, DisplayInfo, Employee, Media, PartnerLibrary, Project,
+        TableSchema, Vendor,
+    };
     use std::cmp::Ordering;
     use std::fmt;
+    use std::str::FromStr;
 
-    #[derive(serde::Deserialize, serde::Serialize, Debug, Default, Clone)]
+    #[derive(serde::Deserialize, serde::Serialize, ts_rs::TS, Debug, Default, Clone)]
+    #[ts(export_to = "../src/bindings/")]
     pub enum PermissionLevel {
         Basic,
         User,
@@ -372,7 +1936,8 @@ pub mod enums {
         None,
     }
 
-    #[derive(serde::Deserialize, serde::Serialize, Debug, Default, Clone)]
+    #[derive(serde::Deserialize, serde::Serialize, ts_rs::TS, Debug, Default, Clone)]
+    #[ts(export_to = "../src/bindings/")]
     pub enum MediaType {
         Book,
         VideoGame,
@@ -395,6 +1960,15 @@ pub mod enums {
     }
 
     impl PermissionLevel {
+        pub const ALL: [PermissionLevel; 6] = [
+            PermissionLevel::Basic,
+            PermissionLevel::User,
+            PermissionLevel::Manager,
+            PermissionLevel::Admin,
+            PermissionLevel::Dev,
+            PermissionLevel::None,
+        ];
+
         fn to_ordinal(&self) -> u8 {
             match self {
                 PermissionLevel::None => 0,
@@ -407,6 +1981,22 @@ pub mod enums {
         }
     }
 
+    impl FromStr for PermissionLevel {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.trim().to_lowercase().as_str() {
+                "basic" => Ok(PermissionLevel::Basic),
+                "user" => Ok(PermissionLevel::User),
+                "manager" => Ok(PermissionLevel::Manager),
+                "admin" => Ok(PermissionLevel::Admin),
+                "dev" => Ok(PermissionLevel::Dev),
+                "none" => Ok(PermissionLevel::None),
+                _ => Err(format!("Unknown permission level: {}", s)),
+            }
+        }
+    }
+
     impl fmt::Display for PermissionLevel {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
             match self {
@@ -420,6 +2010,138 @@ pub mod enums {
         }
     }
 
+    #[derive(serde::Serialize, ts_rs::TS, Debug, Clone, PartialEq)]
+    #[ts(export_to = "../src/bindings/")]
+    pub enum TotpStatus {
+        Verified,
+        EnrolmentRequired,
+        CodeRequired,
+    }
+
+    #[derive(serde::Deserialize, serde::Serialize, ts_rs::TS, Debug, Default, Clone, PartialEq)]
+    #[ts(export_to = "../src/bindings/")]
+    pub enum AuditAction {
+        Insert,
+        #[default]
+        Update,
+        Delete,
+        Checkout,
+        Return,
+    }
+
+    #[derive(serde::Deserialize, serde::Serialize, ts_rs::TS, Debug, Default, Clone, PartialEq)]
+    #[ts(export_to = "../src/bindings/")]
+    pub enum TransferStatus {
+        #[default]
+        Requested,
+        InTransit,
+        Received,
+    }
+
+    #[derive(serde::Deserialize, serde::Serialize, ts_rs::TS, Debug, Default, Clone, PartialEq)]
+    #[ts(export_to = "../src/bindings/")]
+    pub enum InterlibraryLoanStatus {
+        #[default]
+        Requested,
+        SentToPartner,
+        Received,
+        OnLoan,
+        Returned,
+        ShippedBack,
+    }
+
+    #[derive(serde::Deserialize, serde::Serialize, ts_rs::TS, Debug, Default, Clone, PartialEq)]
+    #[ts(export_to = "../src/bindings/")]
+    pub enum PurchaseStatus {
+        #[default]
+        Pending,
+        Approved,
+        Rejected,
+        Received,
+    }
+
+    /// The tables that the generic record commands can reach. Transfers, interlibrary loans
+    /// and purchases only change through their own workflows.
+    #[derive(serde::Deserialize, serde::Serialize, ts_rs::TS, Debug, Clone, Copy, PartialEq)]
+    #[ts(export_to = "../src/bindings/")]
+    pub enum Table {
+        Employee,
+        Media,
+        Branch,
+        PartnerLibrary,
+        Vendor,
+        Department,
+        Project,
+    }
+
+    /// A change made through `App`, sent to everyone subscribed with `App::subscribe`. The
+    /// desktop app forwards them to its windows, so lists can be updated in place.
+    #[derive(serde::Serialize, ts_rs::TS, Debug, Clone)]
+    #[serde(tag = "kind", rename_all = "snake_case")]
+    #[ts(export_to = "../src/bindings/")]
+    pub enum AppEvent {
+        /// A copy was added or changed, including checkouts, returns and transfers
+        MediaChanged { media: Media },
+        MediaRemoved { id: u16 },
+        /// A copy was checked out. It follows the `MediaChanged` for the same copy.
+        LoanCreated {
+            media_id: u16,
+            renter: String,
+            #[ts(type = "number")]
+            due: u64,
+        },
+        /// Events reach every window whoever is signed in, so only the id is sent. Windows
+        /// that show employees fetch them again through the usual permission checks.
+        EmployeeUpdated { id: u16 },
+        EmployeeRemoved { id: u16 },
+        /// The cached tables were loaded again, e.g. after an import or a profile switch.
+        /// Anything on screen may be stale.
+        Reloaded,
+    }
+
+    impl Table {
+        pub const ALL: [Table; 7] = [
+            Table::Employee,
+            Table::Media,
+            Table::Branch,
+            Table::PartnerLibrary,
+            Table::Vendor,
+            Table::Department,
+            Table::Project,
+        ];
+
+        /// The lowest rank that may read the table. Writes always need an Admin.
+        pub fn read_level(self) -> PermissionLevel {
+            match self {
+                Table::Employee => PermissionLevel::Manager,
+                _ => PermissionLevel::Basic,
+            }
+        }
+
+        pub fn schema(self) -> TableSchema {
+            let fields = match self {
+                Table::Employee => Employee::schema(),
+                Table::Media => Media::schema(),
+                Table::Branch => Branch::schema(),
+                Table::PartnerLibrary => PartnerLibrary::schema(),
+                Table::Vendor => Vendor::schema(),
+                Table::Department => Department::schema(),
+                Table::Project => Project::schema(),
+            };
+            TableSchema { table: self, fields }
+        }
+    }
+
+    impl MediaType {
+        pub const ALL: [MediaType; 5] = [
+            MediaType::Book,
+            MediaType::VideoGame,
+            MediaType::Movie,
+            MediaType::Music,
+            MediaType::None,
+        ];
+    }
+
     impl fmt::Display for MediaType {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
             match self {
@@ -439,8 +2161,14 @@ pub mod enums {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::types::enums::PermissionLevel;
-    use crate::types::structs::Trie;
+    use crate::types::enums::{
+        InterlibraryLoanStatus, MediaType, PermissionLevel, Table, TransferStatus,
+    };
+    use crate::types::structs::{
+        DisplayInfo, EmployeeInfo, InterlibraryLoan, LockoutPolicy, LoginRecord,
+        Project, PurchaseRequest, Transfer, Trie, Vendor,
+    };
+    use crate::utils::security;
 
     #[test]
     fn create_employee() {
@@ -497,4 +2225,571 @@ mod tests {
         assert_eq!(trie.search("hello".to_string()), None);
         assert_eq!(trie.starts_with("he".to_string()), None);
     }
+
+    #[test]
+    fn test_progressive_delay() {
+        let policy = LockoutPolicy::default();
+        let mut record = LoginRecord::new(10);
+        assert_eq!(record.retry_after(&policy, 100), None);
+
+        record.register_failure(&policy, 100);
+        assert_eq!(record.retry_after(&policy, 100), Some(1));
+        assert_eq!(record.retry_after(&policy, 101), None);
+
+        record.register_failure(&policy, 101);
+        assert_eq!(record.retry_after(&policy, 101), Some(2));
+    }
+
+    #[test]
+    fn test_lockout() {
+        let policy = LockoutPolicy::default();
+        let mut record = LoginRecord::new(10);
+        for _ in 0..policy.max_attempts {
+            record.register_failure(&policy, 100);
+        }
+        assert_eq!(record.retry_after(&policy, 100), Some(policy.lockout_secs));
+        assert!(record.is_locked(100));
+        assert_eq!(record.retry_after(&policy, 100 + policy.lockout_secs), None);
+        assert!(!record.is_locked(100 + policy.lockout_secs));
+
+        record.register_failure(&policy, 100);
+        record.reset();
+        assert_eq!(record.retry_after(&policy, 100), None);
+        // Failed logins are not data changes
+        assert!(!LoginRecord::audited());
+        assert!(structs::Employee::audited());
+    }
+
+    #[test]
+    fn test_change_password() {
+        let mut employee = structs::Employee::new(
+            10,
+            String::from("John Doe"),
+            String::from("IT"),
+            1,
+            String::from("Ambitious Project"),
+            String::from("Computer Science"),
+            1_000,
+            PermissionLevel::Manager,
+            String::from("password"),
+        )
+        .unwrap();
+        let stamp = employee.session_stamp();
+        assert!(employee.change_password("short", 2).is_err());
+        assert!(employee.change_password("password", 2).is_err());
+        assert_eq!(employee.session_stamp(), stamp);
+        assert!(employee.change_password("password2", 2).is_ok());
+        assert_ne!(employee.session_stamp(), stamp);
+        let stamp = employee.session_stamp();
+        employee.set_perm_level(PermissionLevel::Admin);
+        assert_ne!(employee.session_stamp(), stamp);
+        assert!(employee.change_password("password3", 2).is_ok());
+        assert!(employee.change_password("password", 2).is_err());
+        assert!(employee.change_password("password4", 2).is_ok());
+        assert!(employee.change_password("password", 2).is_ok());
+        assert!(security::verify_password(employee.password(), "password").unwrap());
+    }
+
+    #[test]
+    fn test_reset_code() {
+        let mut employee = structs::Employee::default();
+        let code = employee.issue_reset_code().unwrap();
+        assert!(employee.must_change_password());
+        assert!(!employee.redeem_reset_code("wrong code"));
+        assert!(employee.redeem_reset_code(&code));
+        assert!(!employee.redeem_reset_code(&code));
+        assert!(employee.must_change_password());
+    }
+
+    #[test]
+    fn test_recovery_codes() {
+        let mut employee = structs::Employee::default();
+        employee.set_totp("secret".to_string(), &["abc".to_string(), "def".to_string()]);
+        assert!(!employee.redeem_recovery_code("xyz"));
+        assert!(employee.redeem_recovery_code("abc"));
+        assert!(!employee.redeem_recovery_code("abc"));
+        assert!(employee.redeem_recovery_code(" def "));
+    }
+
+    #[test]
+    fn test_employee_info_hides_secrets() {
+        let mut employee = structs::Employee::new(
+            10,
+            String::from("John Doe"),
+            String::from("IT"),
+            1,
+            String::from("Ambitious Project"),
+            String::from("Computer Science"),
+            1_000,
+            PermissionLevel::Admin,
+            String::from("password"),
+        )
+        .unwrap();
+        employee.issue_reset_code().unwrap();
+        employee.set_totp("encrypted-secret".to_string(), &["recovery".to_string()]);
+
+        let json = serde_json::to_string(&EmployeeInfo::from(&employee)).unwrap();
+        assert!(!json.contains(employee.password()));
+        assert!(!json.contains("$argon2"));
+        assert!(!json.contains("encrypted-secret"));
+        assert!(json.contains("\"totp_enabled\":true"));
+
+        let display = employee.to_string();
+        assert!(!display.contains(employee.password()));
+        assert!(!display.contains("$argon2"));
+    }
+
+    #[test]
+    fn test_transfer_lifecycle() {
+        let mut media = structs::Media::new(
+            7,
+            MediaType::Book,
+            String::from("Dune"),
+            true,
+            String::from("Ace"),
+            String::new(),
+        );
+        media.set_branch_id(1);
+        let mut transfer = Transfer::new(3, &media, 2, 10, 100);
+        assert_eq!(transfer.source_branch(), 1);
+        assert!(transfer.is_open());
+
+        transfer.advance(&mut media, 200).unwrap();
+        assert_eq!(transfer.status(), &TransferStatus::InTransit);
+        assert!(transfer.is_open());
+        assert!(media.in_transit());
+        assert!(!media.is_available());
+        assert!(!media.in_branch(2));
+
+        transfer.advance(&mut media, 300).unwrap();
+        assert_eq!(transfer.status(), &TransferStatus::Received);
+        assert!(!media.in_transit());
+        assert!(media.in_branch(2));
+        assert!(!media.in_branch(1));
+        assert!(!transfer.is_open());
+        assert!(transfer.advance(&mut media, 400).is_err());
+    }
+
+    #[test]
+    fn test_renter_is_tracked_by_id() {
+        let mut employee = structs::Employee::default();
+        employee.set_id(12);
+        employee.set_name(String::from("Jane Doe"));
+        let mut media = structs::Media::default();
+        media.set_borrowable(true);
+
+        media.set_renter(&employee);
+        assert_eq!(media.renter_id(), Some(12));
+        assert_eq!(media.renter(), Some("Jane Doe"));
+        assert!(!media.is_available());
+
+        media.clear_renter();
+        assert_eq!(media.renter_id(), None);
+        assert!(media.is_available());
+    }
+
+    #[test]
+    fn test_interlibrary_loan_lifecycle() {
+        let employee = structs::Employee::default();
+        let mut loan = InterlibraryLoan::new(
+            1,
+            4,
+            String::from("Dune"),
+            MediaType::Book,
+            &employee,
+            None,
+            100,
+        );
+        loan.advance(None, None, 50, 200).unwrap();
+        assert_eq!(loan.status(), &InterlibraryLoanStatus::SentToPartner);
+        assert!(loan.advance(None, None, 50, 300).is_err());
+        assert!(loan.advance(Some(250), None, 50, 300).is_err());
+        loan.advance(Some(1_000), None, 50, 300).unwrap();
+        assert!(loan.is_on_site());
+
+        assert!(loan.advance(None, None, 50, 400).is_err());
+        loan.advance(None, Some("Jane".to_string()), 5_000, 400).unwrap();
+        assert_eq!(loan.status(), &InterlibraryLoanStatus::OnLoan);
+        // The patron cannot keep the item past the partner's due date
+        assert_eq!(loan.patron_due(), Some(1_000));
+        assert!(!loan.is_on_site());
+
+        loan.advance(None, None, 50, 500).unwrap();
+        assert!(loan.is_on_site());
+        loan.advance(None, None, 50, 600).unwrap();
+        assert_eq!(loan.status(), &InterlibraryLoanStatus::ShippedBack);
+        assert!(!loan.is_on_site());
+        assert!(loan.advance(None, None, 50, 700).is_err());
+    }
+
+    #[test]
+    fn test_purchase_budget_and_report() {
+        let mut employee = structs::Employee::new(
+            10,
+            String::from("John Doe"),
+            String::from("IT"),
+            1,
+            String::from("Archive"),
+            String::from("Computer Science"),
+            100,
+            PermissionLevel::User,
+            String::from("password"),
+        )
+        .unwrap();
+        let vendor = Vendor::new(1, String::from("Ace"));
+        let request = |id, price| {
+            let title = String::from("Dune");
+            PurchaseRequest::new(id, &employee, title, MediaType::Book, &vendor, price, 0)
+        };
+        let mut approved = request(1, 60);
+        approved.decide(true, 1).unwrap();
+        assert!(approved.decide(false, 2).is_err());
+        let mut rejected = request(2, 30);
+        rejected.decide(false, 1).unwrap();
+        let pending = request(3, 50);
+        let mut received = request(4, 20);
+        received.decide(true, 1).unwrap();
+        let media = received.receive(99, 2).unwrap();
+        assert_eq!(media.get_id(), 99);
+        assert!(media.is_available());
+        assert!(pending.clone().receive(100, 2).is_err());
+
+        employee.set_project(String::from("Outreach"));
+        let title = String::from("Halo");
+        let mut other =
+            PurchaseRequest::new(5, &employee, title, MediaType::VideoGame, &vendor, 40, 0);
+        other.decide(true, 1).unwrap();
+
+        let requests = vec![approved, rejected, pending, received, other];
+        let budget = PurchaseRequest::budget(&employee, &requests);
+        assert_eq!(budget.committed, 120);
+        assert_eq!(budget.remaining, -20);
+
+        let by_project = PurchaseRequest::spend_report(&requests, true);
+        assert_eq!(by_project.len(), 2);
+        assert_eq!((by_project[0].name.as_str(), by_project[0].spent), ("Archive", 80));
+        assert_eq!((by_project[1].name.as_str(), by_project[1].purchases), ("Outreach", 1));
+        let by_department = PurchaseRequest::spend_report(&requests, false);
+        assert_eq!(by_department[0].spent, 120);
+    }
+
+    #[test]
+    fn test_vendor_merge() {
+        assert_eq!(Vendor::normalize_name(" Sega Corp. "), "sega");
+        assert_eq!(Vendor::normalize_name("SEGA"), "sega");
+        assert_eq!(Vendor::normalize_name("Co."), "co");
+
+        let media = |id, vendor: &str| {
+            let (name, vendor) = (String::from("Sonic"), vendor.to_string());
+            structs::Media::new(id, MediaType::VideoGame, name, true, vendor, String::new())
+        };
+        let mut linked = media(5, "Ace");
+        let existing = Vendor::new(3, String::from("Nintendo"));
+        linked.assign_vendor(&existing);
+        let items = vec![
+            media(1, "Sega"),
+            media(2, "SEGA"),
+            media(3, "Sega Corp."),
+            media(4, "nintendo inc"),
+            media(6, ""),
+            linked,
+        ];
+        let merge = Vendor::merge_free_text(std::slice::from_ref(&existing), &items, 10);
+        assert_eq!(merge.created.len(), 1);
+        assert_eq!(merge.created[0].get_id(), 10);
+        assert_eq!(merge.created[0].get_name(), "Sega");
+        let mut assignments = merge.assignments.clone();
+        assignments.sort_unstable();
+        assert_eq!(assignments, vec![(1, 10), (2, 10), (3, 10), (4, 3)]);
+
+        let mut items = items;
+        for item in items.iter_mut() {
+            if let Some(&(_, vendor_id)) = assignments.iter().find(|(id, _)| *id == item.get_id()) {
+                let vendor = if vendor_id == 3 { &existing } else { &merge.created[0] };
+                item.assign_vendor(vendor);
+            }
+        }
+        let vendors = vec![existing, merge.created[0].clone()];
+        let report = Vendor::report(&vendors, &items, &[]);
+        assert_eq!((report[0].name.as_str(), report[0].items), ("Nintendo", 2));
+        assert_eq!((report[1].name.as_str(), report[1].items), ("Sega", 3));
+    }
+
+    #[test]
+    fn test_project_spending() {
+        let vendor = Vendor::new(1, String::from("Ace"));
+        let archive = Project::new(3, String::from("Archive"), 100, Some(200), 500);
+        assert!(archive.validate().is_ok());
+        assert!(Project::new(4, String::from("Late"), 100, Some(50), 0).validate().is_err());
+
+        let mut employee = structs::Employee::default();
+        employee.assign_project(Some(&archive));
+        assert_eq!(employee.project(), "Archive");
+        let request = |id, price, employee: &structs::Employee| {
+            let title = String::from("Dune");
+            PurchaseRequest::new(id, employee, title, MediaType::Book, &vendor, price, 0)
+        };
+        let mut approved = request(1, 120, &employee);
+        approved.decide(true, 1).unwrap();
+        let pending = request(2, 80, &employee);
+        employee.assign_project(None);
+        assert_eq!(employee.project_id(), None);
+
+        let report = Project::spending(&[archive], &[approved, pending]);
+        assert_eq!(report[0].spent, 120);
+        assert_eq!(report[0].remaining, 380);
+    }
+
+    #[test]
+    fn test_record_validation() {
+        let vendor = Vendor::new(1, String::from("  "));
+        assert_eq!(vendor.validate().unwrap_err(), "A vendor name is required");
+        assert!(Vendor::new(1, String::from("Ace")).validate().is_ok());
+
+        let mut employee = structs::Employee::default();
+        assert!(employee.validate().is_err());
+        employee.set_name(String::from("Ann"));
+        assert!(employee.validate().is_ok());
+        // Without a password nothing can log in until a reset code is issued
+        employee.additional_setup();
+        assert!(employee.password().is_empty());
+        let fields = Table::Employee.schema().fields;
+        assert!(!fields.iter().any(|field| field.name == "totp_secret"));
+        assert!(fields.iter().any(|field| field.name == "password" && field.read_only));
+        assert!(fields.iter().any(|field| field.name == "name" && field.required));
+
+        assert_eq!(Table::Employee.read_level(), PermissionLevel::Manager);
+        assert_eq!(Table::Media.read_level(), PermissionLevel::Basic);
+        assert_eq!(
+            serde_json::from_str::<Table>("\"PartnerLibrary\"").unwrap(),
+            Table::PartnerLibrary
+        );
+    }
+
+    #[test]
+    fn test_schema_matches_records() {
+        let defaults = |table: Table| match table {
+            Table::Employee => serde_json::to_value(structs::Employee::default()),
+            Table::Media => serde_json::to_value(structs::Media::default()),
+            Table::Branch => serde_json::to_value(structs::Branch::default()),
+            Table::PartnerLibrary => serde_json::to_value(structs::PartnerLibrary::default()),
+            Table::Vendor => serde_json::to_value(Vendor::default()),
+            Table::Department => serde_json::to_value(structs::Department::default()),
+            Table::Project => serde_json::to_value(Project::default()),
+        };
+        for table in Table::ALL {
+            let record = defaults(table).unwrap();
+            let record = record.as_object().unwrap();
+            let schema = table.schema();
+            let mut names: Vec<&str> = schema.fields.iter().map(|field| field.name).collect();
+            assert!(names.contains(&"id"), "{:?} has no id", table);
+            for name in &names {
+                assert!(record.contains_key(*name), "{:?} has no field {}", table, name);
+            }
+            // Everything a form cannot see is a secret that has its own flow
+            let mut hidden: Vec<&str> = record
+                .keys()
+                .map(String::as_str)
+                .filter(|key| !names.contains(key))
+                .collect();
+            hidden.sort_unstable();
+            let expected: &[&str] = match table {
+                Table::Employee => {
+                    &["password_history", "recovery_codes", "reset_code", "totp_secret"]
+                }
+                _ => &[],
+            };
+            assert_eq!(hidden, expected, "{:?}", table);
+            names.dedup();
+            assert_eq!(names.len(), schema.fields.len());
+        }
+    }
+
+    #[test]
+    fn test_field_schema() {
+        let media_type = structs::FieldSchema::choice("media_type", &MediaType::ALL);
+        assert_eq!(media_type.parse("video game").unwrap(), "VideoGame");
+        assert_eq!(media_type.parse("book").unwrap(), "Book");
+        assert!(media_type.parse("Vinyl").is_err());
+        assert!(media_type.check(&serde_json::json!("Movie")).is_ok());
+        assert_eq!(
+            media_type.check(&serde_json::json!("Vinyl")).unwrap_err(),
+            "media_type must be one of Book, VideoGame, Movie, Music, None"
+        );
+
+        let end = structs::FieldSchema::timestamp("end").optional();
+        assert_eq!(end.parse("").unwrap(), serde_json::Value::Null);
+        assert_eq!(end.parse("1700000000").unwrap(), 1700000000);
+        assert!(end.check(&serde_json::Value::Null).is_ok());
+        assert!(structs::FieldSchema::timestamp("start").check(&serde_json::Value::Null).is_err());
+
+        let budget = structs::FieldSchema::integer("alloc_budget", u16::MAX);
+        assert!(budget.check(&serde_json::json!(65535)).is_ok());
+        assert!(budget.check(&serde_json::json!(65536)).is_err());
+        assert!(budget.check(&serde_json::json!(-1)).is_err());
+        assert!(budget.parse("ten").is_err());
+
+        let password = structs::FieldSchema::new("password", structs::FieldKind::Password)
+            .required()
+            .min(8);
+        assert_eq!(
+            password.check(&serde_json::json!("short")).unwrap_err(),
+            "password must be at least 8 characters"
+        );
+        assert!(password.check(&serde_json::json!("long enough")).is_ok());
+        let name = structs::FieldSchema::text("name").required();
+        assert_eq!(name.check(&serde_json::json!(" ")).unwrap_err(), "name is required");
+
+        let json = serde_json::to_value(structs::FieldSchema::reference("vendor_id", Table::Vendor))
+            .unwrap();
+        assert_eq!(json["type"], "reference");
+        assert_eq!(json["table"], "Vendor");
+        assert_eq!(json["max"], 65535);
+    }
+
+    #[test]
+    fn test_app_event_json() {
+        let event = enums::AppEvent::LoanCreated {
+            media_id: 4,
+            renter: String::from("Jane"),
+            due: 1_700_000_000,
+        };
+        assert_eq!(
+            serde_json::to_value(event).unwrap(),
+            serde_json::json!({
+                "kind": "loan_created",
+                "media_id": 4,
+                "renter": "Jane",
+                "due": 1_700_000_000,
+            })
+        );
+        let json = serde_json::to_value(enums::AppEvent::MediaRemoved { id: 2 }).unwrap();
+        assert_eq!(json, serde_json::json!({"kind": "media_removed", "id": 2}));
+        let json = serde_json::to_value(enums::AppEvent::EmployeeUpdated { id: 3 }).unwrap();
+        assert_eq!(json, serde_json::json!({"kind": "employee_updated", "id": 3}));
+        let json = serde_json::to_value(enums::AppEvent::Reloaded).unwrap();
+        assert_eq!(json, serde_json::json!({"kind": "reloaded"}));
+    }
+
+    /// Employee rows carry password hashes and TOTP secrets, so no command may return one.
+    #[test]
+    fn test_commands_never_return_employee() {
+        let commands = include_str!("main.rs").split("#[tauri::command]").skip(1);
+        for command in commands {
+            let signature = &command[..command.find('{').unwrap()];
+            let returns = signature.split("->").nth(1).unwrap_or_default();
+            let leaks = returns
+                .split(|c: char| !c.is_alphanumeric() && c != '_')
+                .any(|word| word == "Employee");
+            assert!(!leaks, "Command returns Employee: {}", signature.trim());
+        }
+    }
+
+    /// The generated TypeScript for every type the Tauri commands take or return, keyed by
+    /// the path it is exported to.
+    macro_rules! bindings {
+        ($($ty:ty),* $(,)?) => {
+            vec![$((
+                <$ty as ts_rs::TS>::EXPORT_TO.unwrap(),
+                <$ty as ts_rs::TS>::export_to_string().unwrap(),
+            )),*]
+        };
+    }
+
+    /// The names of the types in `source` that derive or implement `ts_rs::TS`.
+    fn ts_types(source: &str) -> Vec<String> {
+        let mut names = Vec::new();
+        let mut derives_ts = false;
+        for line in source.lines().map(str::trim) {
+            if line.starts_with("#[derive(") {
+                derives_ts = line
+                    .split(|c: char| !c.is_alphanumeric() && c != '_')
+                    .any(|word| word == "TS");
+            } else if let Some(name) = line.strip_prefix("impl ts_rs::TS for ") {
+                names.push(name.trim_end_matches(" {").to_string());
+            } else if derives_ts && !line.starts_with("#[") && !line.starts_with("///") {
+                let mut words = line.split_whitespace();
+                if words.any(|word| word == "struct" || word == "enum") {
+                    let name = words.next().unwrap_or_default();
+                    names.push(name.trim_end_matches(|c: char| !c.is_alphanumeric()).to_string());
+                }
+                derives_ts = false;
+            }
+        }
+        names
+    }
+
+    #[test]
+    fn test_bindings_up_to_date() {
+        use crate::{config, setup};
+        use std::path::Path;
+
+        let bindings = bindings![
+            EmployeeInfo, structs::Media, structs::Branch, Transfer,
+            structs::PartnerLibrary, InterlibraryLoan, PurchaseRequest, structs::Department,
+            Project, structs::ProjectSpend, structs::RegistryMigration, Vendor,
+            structs::VendorMerge, structs::VendorReport, structs::BudgetSummary,
+            structs::SpendReport, structs::OrgNode, structs::OrgChart, structs::SearchResult,
+            structs::AuditEntry, structs::AuditFilter, structs::TotpEnrolment,
+            structs::TableSchema, structs::FieldSchema, structs::FieldKind, structs::Choice,
+            PermissionLevel, MediaType, enums::TotpStatus, enums::AuditAction, TransferStatus,
+            InterlibraryLoanStatus, enums::PurchaseStatus, Table, enums::AppEvent,
+            config::UiConfig,
+            config::ProfileList, setup::SetupStatus, setup::TableReport,
+        ];
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let update = std::env::var_os("UPDATE_BINDINGS").is_some();
+
+        // A type that derives TS but is missing above would never have its binding written
+        let listed: Vec<_> = bindings
+            .iter()
+            .filter_map(|(path, _)| Path::new(path).file_stem()?.to_str())
+            .collect();
+        for directory in ["src", "src/bin"] {
+            for entry in std::fs::read_dir(root.join(directory)).unwrap() {
+                let path = entry.unwrap().path();
+                if path.extension().is_none_or(|extension| extension != "rs") {
+                    continue;
+                }
+                for name in ts_types(&std::fs::read_to_string(&path).unwrap()) {
+                    assert!(
+                        listed.contains(&name.as_str()),
+                        "{} derives TS but is not in the bindings list of test_bindings_up_to_date",
+                        name
+                    );
+                }
+            }
+        }
+
+        let mut stale = Vec::new();
+        for (path, contents) in &bindings {
+            let path = root.join(path);
+            if update {
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(&path, contents).unwrap();
+            } else if std::fs::read_to_string(&path).ok().as_ref() != Some(contents) {
+                stale.push(path.display().to_string());
+            }
+        }
+        // Files left behind by types that are no longer exported
+        let exported: Vec<_> = bindings.iter().map(|(path, _)| root.join(path)).collect();
+        let directory = root.join(Path::new(bindings[0].0).parent().unwrap());
+        for entry in std::fs::read_dir(&directory).unwrap() {
+            let path = entry.unwrap().path();
+            if !exported.contains(&path) {
+                if update {
+                    std::fs::remove_file(&path).unwrap();
+                } else {
+                    stale.push(path.display().to_string());
+                }
+            }
+        }
+
+        assert!(
+            stale.is_empty(),
+            "TypeScript bindings are out of date: {}\n\
+             Regenerate them with `UPDATE_BINDINGS=1 cargo test test_bindings_up_to_date`",
+            stale.join(", ")
+        );
+    }
 }
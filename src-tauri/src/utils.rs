@@ -1,43 +1,140 @@
 pub mod loading {
     use super::super::types::structs::Trie;
+    use super::crypto;
     use crate::types::structs::DisplayInfo;
+    use data_encoding::BASE64;
+    use rand::RngCore;
     use std::collections::HashMap;
-    use std::fs::File;
-    use std::io::prelude::*;
-    use std::io::BufReader;
+    use std::path::{Path, PathBuf};
+    use std::sync::OnceLock;
+
+    static CONFIG_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+    /// Sets the config file used for the rest of the process. Only the first call has an effect.
+    pub fn set_config_file(path: PathBuf) {
+        let _ = CONFIG_FILE.set(path);
+    }
+
+    pub fn config_file() -> &'static Path {
+        CONFIG_FILE.get_or_init(|| PathBuf::from("./resources/config.ini"))
+    }
 
-    pub fn load_db_settings() -> Result<Vec<String>, String> {
-        let settings: [&str; 3] = ["endpoint", "api_key", "salt"];
-        load_settings(&settings)
+    /// The machine keyfile is kept next to the config file it unlocks.
+    fn key_file() -> PathBuf {
+        config_file().with_file_name("machine.key")
+    }
+
+    /// Settings that may be stored encrypted, as (section, setting) pairs. The `api_key` of
+    /// every `[profile.<name>]` section is secret as well.
+    pub const SECRET_SETTINGS: [(&str, &str); 2] = [("DBSettings", "api_key"), ("Security", "totp_key")];
+
+    fn load_config_contents() -> Result<String, String> {
+        std::fs::read_to_string(config_file()).map_err(|e| format!("Failed to read config file: {}", e))
+    }
+
+    /// Loads the key for encrypted settings. `RLM_CONFIG_PASSPHRASE` takes precedence over the
+    /// machine keyfile, which is only generated when `create` is set.
+    pub fn load_config_key(create: bool) -> Result<[u8; 32], String> {
+        if let Ok(passphrase) = std::env::var("RLM_CONFIG_PASSPHRASE") {
+            return crypto::derive_key(&passphrase).map_err(|e| e.to_string());
+        }
+        match std::fs::read_to_string(key_file()) {
+            Ok(encoded) => {
+                let bytes = BASE64
+                    .decode(encoded.trim().as_bytes())
+                    .map_err(|_| "Machine keyfile is corrupt".to_string())?;
+                bytes
+                    .try_into()
+                    .map_err(|_| "Machine keyfile is corrupt".to_string())
+            }
+            Err(_) if create => {
+                let mut key = [0u8; 32];
+                rand::thread_rng().fill_bytes(&mut key);
+                std::fs::write(key_file(), BASE64.encode(&key))
+                    .map_err(|e| format!("Failed to write machine keyfile: {}", e))?;
+                Ok(key)
+            }
+            Err(_) => Err("No RLM_CONFIG_PASSPHRASE set and no machine keyfile found".to_string()),
+        }
     }
 
-    fn load_config_contents() -> String {
-        let mut contents = String::new();
-        BufReader::new(File::open("./resources/config.ini").expect("Config file does not exist"))
-            .read_to_string(&mut contents)
-            .expect("Config file is corrupt");
-        contents
+    /// Returns the plaintext of a setting, decrypting it first if it is encrypted.
+    pub fn reveal_setting(value: &str) -> Result<String, String> {
+        if !crypto::is_encrypted(value) {
+            return Ok(value.to_string());
+        }
+        crypto::decrypt_setting(&load_config_key(false)?, value)
     }
 
-    fn load_settings(settings: &[&str]) -> Result<Vec<String>, String> {
-        let contents = load_config_contents(); // Ensure this function returns a Result or handles errors internally
+    /// Encrypts a secret setting in the config file. Without a `value` the current plaintext
+    /// value is encrypted in place.
+    pub fn store_secret_setting(section: &str, setting: &str, value: Option<&str>) -> Result<(), String> {
+        let profile_key =
+            section.to_lowercase().starts_with("profile.") && setting == "api_key";
+        if !profile_key && !SECRET_SETTINGS.contains(&(section, setting)) {
+            return Err(format!("{} is not a secret setting", setting));
+        }
+        let contents = load_config_contents()?;
         let mut config = configparser::ini::Ini::new();
-        config.read(contents).map_err(|e| e.to_string())?;
+        config.read(contents)?;
+        let current = config.get(section, setting).unwrap_or_default();
+        let plaintext = match value {
+            Some(value) => value.to_string(),
+            None if crypto::is_encrypted(&current) => return Ok(()),
+            None => current,
+        };
+        let encrypted = crypto::encrypt_setting(&load_config_key(true)?, &plaintext);
+        store_setting(section, setting, &encrypted)
+    }
 
-        settings
-            .iter()
-            .map(|&setting| {
-                config
-                    .get("DBSettings", setting)
-                    .ok_or_else(|| format!("Setting not found: {}", setting))
+    /// Overwrites a setting that already exists in the config file.
+    pub fn store_setting(section: &str, setting: &str, value: &str) -> Result<(), String> {
+        let contents = replace_setting(&load_config_contents()?, section, setting, value)
+            .ok_or_else(|| format!("Setting not found: {}", setting))?;
+        std::fs::write(config_file(), contents).map_err(|e| format!("Failed to write config: {}", e))
+    }
+
+    /// Rewrites a single `setting = value` line, leaving the rest of the file untouched.
+    pub fn replace_setting(contents: &str, section: &str, setting: &str, value: &str) -> Option<String> {
+        let mut current_section = "";
+        let mut replaced = false;
+        let lines: Vec<String> = contents
+            .lines()
+            .map(|line| {
+                let trimmed = line.trim();
+                if trimmed.starts_with('[') && trimmed.ends_with(']') {
+                    current_section = &trimmed[1..trimmed.len() - 1];
+                } else if current_section.eq_ignore_ascii_case(section)
+                    && trimmed.split(['=', ':']).next().map(str::trim) == Some(setting)
+                {
+                    replaced = true;
+                    return format!("{} = {}", setting, value);
+                }
+                line.to_string()
             })
-            .collect()
+            .collect();
+        replaced.then(|| lines.join("\n") + "\n")
     }
 
     pub fn vec_to_hashmap<T: DisplayInfo>(obj_vec: Vec<T>) -> HashMap<u16, T> {
         obj_vec.into_iter().map(|obj| (obj.get_id(), obj)).collect()
     }
 
+    /// Collects the values of an id keyed map, e.g. the media cache.
+    ///
+    /// ```
+    /// use rusty_library_management::types::enums::MediaType;
+    /// use rusty_library_management::types::structs::Media;
+    /// use rusty_library_management::utils::loading::hashmap_to_vec;
+    /// use std::collections::HashMap;
+    ///
+    /// let mut media_guard: HashMap<u16, Media> = HashMap::new();
+    /// let media = Media::new(1, MediaType::Book, "Dune".into(), true, "Ace".into(), "".into());
+    /// media_guard.insert(1, media);
+    ///
+    /// let new_media: Vec<Media> = hashmap_to_vec(&media_guard);
+    /// assert_eq!(new_media.len(), 1);
+    /// ```
     pub fn hashmap_to_vec<T: Clone>(map: &HashMap<u16, T>) -> Vec<T> {
         map.values().cloned().collect()
     }
@@ -51,13 +148,59 @@ pub mod loading {
     }
 }
 
+pub mod diff {
+    use serde_json::{Map, Value};
+
+    /// Builds an object holding the `before` and `after` value of every top-level field that
+    /// differs between the two snapshots. Fields listed in `redacted` are recorded as changed
+    /// without revealing their values.
+    pub fn json_diff(before: Option<&Value>, after: Option<&Value>, redacted: &[&str]) -> Value {
+        let empty = Map::new();
+        let before = before.and_then(Value::as_object).unwrap_or(&empty);
+        let after = after.and_then(Value::as_object).unwrap_or(&empty);
+
+        let mut changes = Map::new();
+        for key in before.keys().chain(after.keys().filter(|k| !before.contains_key(*k))) {
+            let old = before.get(key).unwrap_or(&Value::Null);
+            let new = after.get(key).unwrap_or(&Value::Null);
+            if old == new {
+                continue;
+            }
+            let (old, new) = if redacted.contains(&key.as_str()) {
+                let hidden = Value::String("[redacted]".into());
+                (hidden.clone(), hidden)
+            } else {
+                (old.clone(), new.clone())
+            };
+            let mut change = Map::new();
+            change.insert("before".into(), old);
+            change.insert("after".into(), new);
+            changes.insert(key.clone(), Value::Object(change));
+        }
+        Value::Object(changes)
+    }
+}
+
 pub mod network {
     pub async fn test_network(url: &str) -> bool {
         reqwest::get(url).await.is_ok()
     }
 }
 
+pub mod time {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// Current time as seconds since the Unix epoch.
+    pub fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    }
+}
+
 pub mod user {
+    use serde_json::Value;
     use std::io;
 
     pub fn get_input() -> String {
@@ -68,19 +211,73 @@ pub mod user {
             .unwrap_or_else(|| String::new().parse().unwrap());
         buffer.trim().to_string()
     }
+
+    /// Converts typed-in text to a JSON value of the same kind as `original`. Fields without
+    /// a fixed kind (null, lists and objects) take JSON, falling back to plain text.
+    pub fn parse_value(original: &Value, input: &str) -> Result<Value, String> {
+        match original {
+            Value::Bool(_) => input
+                .parse()
+                .map(Value::Bool)
+                .map_err(|_| "Expected a boolean value".to_string()),
+            Value::Number(_) => input
+                .parse()
+                .map(Value::Number)
+                .map_err(|_| "Expected a numeric value".to_string()),
+            Value::String(_) => Ok(Value::String(input.to_string())),
+            _ => Ok(serde_json::from_str(input)
+                .unwrap_or_else(|_| Value::String(input.to_string()))),
+        }
+    }
 }
 
 pub mod security {
     use argon2::{self, Config, ThreadMode, Variant, Version};
+    use rand::distributions::{Alphanumeric, DistString};
     use rand::RngCore;
+    use std::sync::OnceLock;
+
+    static HASH_PARAMS: OnceLock<HashParams> = OnceLock::new();
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct HashParams {
+        pub variant: Variant,
+        pub mem_cost: u32,
+        pub time_cost: u32,
+        pub lanes: u32,
+    }
+
+    impl Default for HashParams {
+        fn default() -> Self {
+            HashParams {
+                variant: Variant::Argon2i,
+                mem_cost: 65536,
+                time_cost: 10,
+                lanes: 4,
+            }
+        }
+    }
+
+    /// Sets the parameters used for new hashes. Only the first call has an effect.
+    pub fn configure(params: HashParams) {
+        let _ = HASH_PARAMS.set(params);
+    }
+
+    pub fn current_params() -> &'static HashParams {
+        HASH_PARAMS.get_or_init(HashParams::default)
+    }
 
     pub fn hash_str(s: &str) -> Result<String, argon2::Error> {
+        hash_with(s, current_params())
+    }
+
+    pub fn hash_with(s: &str, params: &HashParams) -> Result<String, argon2::Error> {
         let config = Config {
-            variant: Variant::Argon2i,
+            variant: params.variant,
             version: Version::Version13,
-            mem_cost: 65536,
-            time_cost: 10,
-            lanes: 4,
+            mem_cost: params.mem_cost,
+            time_cost: params.time_cost,
+            lanes: params.lanes,
             thread_mode: ThreadMode::Parallel,
             secret: &[],
             ad: &[],
@@ -98,6 +295,406 @@ pub mod security {
     pub fn verify_password(hashed: &str, password: &str) -> Result<bool, argon2::Error> {
         argon2::verify_encoded(hashed, password.as_bytes())
     }
+
+    /// Generates a random alphanumeric code, e.g. for one-time password resets.
+    pub fn generate_code(len: usize) -> String {
+        Alphanumeric.sample_string(&mut rand::thread_rng(), len)
+    }
+
+    /// Checks whether an encoded hash was produced with a different variant, version or cost
+    /// than the current parameters. Hashes that cannot be parsed always need rehashing.
+    pub fn needs_rehash(hashed: &str) -> bool {
+        needs_rehash_with(hashed, current_params())
+    }
+
+    pub fn needs_rehash_with(hashed: &str, params: &HashParams) -> bool {
+        // Encoded hashes look like $argon2i$v=19$m=65536,t=10,p=4$<salt>$<hash>
+        let mut parts = hashed.split('$').skip(1);
+        let variant = parts.next().and_then(|v| Variant::from_str(v).ok());
+        let version = parts.next().and_then(|v| v.strip_prefix("v=")?.parse::<u32>().ok());
+        let costs: Vec<Option<u32>> = parts
+            .next()
+            .unwrap_or_default()
+            .split(',')
+            .map(|cost| cost.get(2..)?.parse().ok())
+            .collect();
+
+        variant != Some(params.variant)
+            || version != Some(Version::Version13.as_u32())
+            || costs != [Some(params.mem_cost), Some(params.time_cost), Some(params.lanes)]
+    }
+}
+
+pub mod crypto {
+    use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+    use aes_gcm::{Aes256Gcm, Key, Nonce};
+    use data_encoding::BASE64;
+    use sha2::{Digest, Sha256};
+
+    const NONCE_LEN: usize = 12;
+    const SETTING_PREFIX: &str = "enc:";
+
+    /// Stretches a passphrase into a 256-bit key. The salt is fixed so that every install
+    /// configured with the same passphrase derives the same key.
+    pub fn derive_key(passphrase: &str) -> Result<[u8; 32], argon2::Error> {
+        let config = argon2::Config {
+            variant: argon2::Variant::Argon2id,
+            hash_length: 32,
+            ..argon2::Config::default()
+        };
+        let raw = argon2::hash_raw(passphrase.as_bytes(), b"rusty-library-management", &config)?;
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&raw);
+        Ok(key)
+    }
+
+    /// Encrypts with AES-256-GCM and returns the nonce and ciphertext as base64.
+    pub fn encrypt(key: &[u8; 32], plaintext: &[u8]) -> String {
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut out = nonce.to_vec();
+        out.extend(cipher.encrypt(&nonce, plaintext).expect("Encryption failed"));
+        BASE64.encode(&out)
+    }
+
+    pub fn decrypt(key: &[u8; 32], encoded: &str) -> Result<Vec<u8>, String> {
+        let data = BASE64
+            .decode(encoded.as_bytes())
+            .map_err(|_| "Encrypted value is not valid base64".to_string())?;
+        if data.len() < NONCE_LEN {
+            return Err("Encrypted value is too short".to_string());
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "Failed to decrypt value, is the key correct?".to_string())
+    }
+
+    pub fn is_encrypted(setting: &str) -> bool {
+        setting.starts_with(SETTING_PREFIX)
+    }
+
+    pub fn encrypt_setting(key: &[u8; 32], value: &str) -> String {
+        format!("{}{}", SETTING_PREFIX, encrypt(key, value.as_bytes()))
+    }
+
+    pub fn decrypt_setting(key: &[u8; 32], setting: &str) -> Result<String, String> {
+        let encoded = setting.strip_prefix(SETTING_PREFIX).unwrap_or(setting);
+        String::from_utf8(decrypt(key, encoded)?)
+            .map_err(|_| "Decrypted setting is not valid UTF-8".to_string())
+    }
+
+    /// Hex encoded SHA-256, for secrets with enough entropy that a slow hash is not needed.
+    pub fn sha256_hex(data: &str) -> String {
+        data_encoding::HEXLOWER.encode(&Sha256::digest(data.as_bytes()))
+    }
+}
+
+pub mod totp {
+    use data_encoding::BASE32_NOPAD;
+    use hmac::{Hmac, Mac};
+    use rand::RngCore;
+    use sha1::Sha1;
+
+    const STEP_SECS: u64 = 30;
+    const DIGITS: u32 = 6;
+
+    pub fn generate_secret() -> Vec<u8> {
+        let mut secret = vec![0u8; 20];
+        rand::thread_rng().fill_bytes(&mut secret);
+        secret
+    }
+
+    pub fn encode_secret(secret: &[u8]) -> String {
+        BASE32_NOPAD.encode(secret)
+    }
+
+    /// Builds the otpauth:// URI that authenticator apps read from a QR code.
+    pub fn provisioning_uri(secret: &[u8], account: &str, issuer: &str) -> String {
+        let label = format!("{}:{}", issuer, account).replace(' ', "%20");
+        format!(
+            "otpauth://totp/{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+            label,
+            encode_secret(secret),
+            issuer.replace(' ', "%20"),
+            DIGITS,
+            STEP_SECS
+        )
+    }
+
+    /// Computes the RFC 6238 code for the time step containing `time`.
+    pub fn code_at(secret: &[u8], time: u64, digits: u32) -> String {
+        let counter = time / STEP_SECS;
+        let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts any key length");
+        mac.update(&counter.to_be_bytes());
+        let hash = mac.finalize().into_bytes();
+
+        // Dynamic truncation from RFC 4226
+        let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+        let binary = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]])
+            & 0x7fff_ffff;
+        format!("{:0width$}", binary % 10u32.pow(digits), width = digits as usize)
+    }
+
+    /// Accepts the code for the current time step or its immediate neighbours to allow for
+    /// clock drift, and returns the step it matched. Steps up to `last_step` have been used
+    /// already and are refused, so a code cannot be replayed.
+    pub fn verify(secret: &[u8], code: &str, now: u64, last_step: Option<u64>) -> Option<u64> {
+        let code = code.trim().as_bytes();
+        let mut matched = None;
+        // Every step is compared, so the timing does not tell which one matched
+        for time in [now.saturating_sub(STEP_SECS), now, now + STEP_SECS] {
+            let step = time / STEP_SECS;
+            if constant_time_eq(code_at(secret, time, DIGITS).as_bytes(), code)
+                && last_step.is_none_or(|last| step > last)
+            {
+                matched = matched.or(Some(step));
+            }
+        }
+        matched
+    }
+
+    fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+    }
+}
+
+/// Helpers for moving free-text columns over to records in their own table.
+pub mod migrate {
+    use std::collections::HashMap;
+
+    /// The records to create, as (id, name), and the (row id, record id) links to write.
+    #[derive(Debug, Default)]
+    pub struct NameMerge {
+        pub created: Vec<(u16, String)>,
+        pub assignments: Vec<(u16, u16)>,
+    }
+
+    /// Matches free-text `values`, given as (row id, text), against `existing` records, given
+    /// as (id, name), after running both through `normalize`. Texts without a match get a new
+    /// record named after their most common spelling, with ids counting up from `next_id`.
+    /// Blank texts are skipped.
+    pub fn merge_names(
+        existing: &[(u16, &str)],
+        values: &[(u16, &str)],
+        next_id: u16,
+        normalize: impl Fn(&str) -> String,
+    ) -> NameMerge {
+        let known: HashMap<String, u16> = existing
+            .iter()
+            .map(|&(id, name)| (normalize(name), id))
+            .collect();
+        let mut unmatched: HashMap<String, Vec<(u16, &str)>> = HashMap::new();
+        let mut merge = NameMerge::default();
+        for &(row_id, text) in values {
+            let key = normalize(text);
+            if key.is_empty() {
+                continue;
+            }
+            match known.get(&key) {
+                Some(&id) => merge.assignments.push((row_id, id)),
+                None => unmatched.entry(key).or_default().push((row_id, text.trim())),
+            }
+        }
+
+        let mut groups: Vec<(String, Vec<(u16, &str)>)> = unmatched.into_iter().collect();
+        groups.sort_by(|a, b| a.0.cmp(&b.0));
+        for (id, (_, rows)) in (next_id..).zip(groups) {
+            // (spelling, count), in the order the spellings were first seen
+            let mut spellings: Vec<(&str, usize)> = vec![];
+            for &(_, spelling) in &rows {
+                match spellings.iter_mut().find(|(name, _)| *name == spelling) {
+                    Some((_, count)) => *count += 1,
+                    None => spellings.push((spelling, 1)),
+                }
+            }
+            // Ties go to the spelling seen first
+            let name = spellings
+                .iter()
+                .rev()
+                .max_by_key(|(_, count)| *count)
+                .map(|(name, _)| name.to_string())
+                .unwrap_or_default();
+            merge.created.push((id, name));
+            merge.assignments.extend(rows.iter().map(|&(row_id, _)| (row_id, id)));
+        }
+        merge
+    }
+
+    /// Lower case with whitespace collapsed, for names where punctuation matters.
+    pub fn normalize_whitespace(name: &str) -> String {
+        name.split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ")
+            .to_lowercase()
+    }
+}
+
+/// Queries over the reporting tree formed by `Employee.boss_id`. Employees at the top of
+/// the tree are their own boss, or have a `boss_id` of 0 like rows written before that.
+pub mod org {
+    use crate::types::enums::PermissionLevel;
+    use crate::types::structs::{DisplayInfo, Employee, EmployeeInfo, OrgChart, OrgNode};
+    use std::collections::{HashMap, HashSet};
+
+    fn is_root(employee: &Employee) -> bool {
+        employee.boss_id() == employee.get_id() || employee.boss_id() == 0
+    }
+
+    pub fn direct_reports(employees: &HashMap<u16, Employee>, id: u16) -> Vec<u16> {
+        let mut reports: Vec<u16> = employees
+            .values()
+            .filter(|emp| emp.boss_id() == id && !is_root(emp))
+            .map(|emp| emp.get_id())
+            .collect();
+        reports.sort_unstable();
+        reports
+    }
+
+    /// Everyone below `id` in the tree, nearest first.
+    pub fn all_reports(employees: &HashMap<u16, Employee>, id: u16) -> Vec<u16> {
+        let mut seen = HashSet::from([id]);
+        let mut reports = vec![];
+        let mut next = 0;
+        let mut current = id;
+        loop {
+            for report in direct_reports(employees, current) {
+                if seen.insert(report) {
+                    reports.push(report);
+                }
+            }
+            let Some(&report) = reports.get(next) else {
+                return reports;
+            };
+            current = report;
+            next += 1;
+        }
+    }
+
+    /// The bosses above `id`, from its direct boss up to the top of the tree.
+    pub fn chain_of_command(
+        employees: &HashMap<u16, Employee>,
+        id: u16,
+    ) -> Result<Vec<u16>, String> {
+        let mut chain = vec![];
+        let mut current = employees
+            .get(&id)
+            .ok_or_else(|| format!("Employee {} not found", id))?;
+        while !is_root(current) {
+            let boss_id = current.boss_id();
+            if boss_id == id || chain.contains(&boss_id) {
+                return Err(format!("Employee {} is part of a reporting cycle", boss_id));
+            }
+            current = employees
+                .get(&boss_id)
+                .ok_or_else(|| format!("Boss {} not found", boss_id))?;
+            chain.push(boss_id);
+        }
+        Ok(chain)
+    }
+
+    /// Whether `manager` is somewhere above `id` in the tree.
+    pub fn is_above(employees: &HashMap<u16, Employee>, manager: u16, id: u16) -> bool {
+        manager != id && all_reports(employees, manager).contains(&id)
+    }
+
+    /// Whether `manager` may look after `id`: Admins after anyone, Managers after the
+    /// employees below them in the tree.
+    pub fn has_authority(employees: &HashMap<u16, Employee>, manager: &Employee, id: u16) -> bool {
+        let level = manager.perm_level();
+        *level >= PermissionLevel::Admin
+            || (*level >= PermissionLevel::Manager && is_above(employees, manager.get_id(), id))
+    }
+
+    /// Like `has_authority`, for changes to the account itself. Those also need `id` to rank
+    /// below `manager`, so nobody can take over the account of someone above their rank that
+    /// happens to report to them.
+    pub fn can_change(employees: &HashMap<u16, Employee>, manager: &Employee, id: u16) -> bool {
+        has_authority(employees, manager, id)
+            && employees
+                .get(&id)
+                .is_some_and(|employee| employee.perm_level() < manager.perm_level())
+    }
+
+    /// Employees whose boss does not exist. Roots are never orphans.
+    pub fn orphans(employees: &HashMap<u16, Employee>) -> Vec<u16> {
+        let mut orphans: Vec<u16> = employees
+            .values()
+            .filter(|emp| !is_root(emp) && !employees.contains_key(&emp.boss_id()))
+            .map(|emp| emp.get_id())
+            .collect();
+        orphans.sort_unstable();
+        orphans
+    }
+
+    /// Groups of employees that report to each other in a loop, each starting with its
+    /// lowest id.
+    pub fn cycles(employees: &HashMap<u16, Employee>) -> Vec<Vec<u16>> {
+        let mut done: HashSet<u16> = HashSet::new();
+        let mut cycles = vec![];
+        let mut ids: Vec<u16> = employees.keys().copied().collect();
+        ids.sort_unstable();
+        for start in ids {
+            let mut path: Vec<u16> = vec![];
+            let mut current = start;
+            while let Some(employee) = employees.get(&current) {
+                if done.contains(&current) || is_root(employee) {
+                    break;
+                }
+                if let Some(pos) = path.iter().position(|&id| id == current) {
+                    let mut cycle = path.split_off(pos);
+                    let lowest = (0..cycle.len()).min_by_key(|&i| cycle[i]).unwrap_or(0);
+                    cycle.rotate_left(lowest);
+                    done.extend(cycle.iter().copied());
+                    cycles.push(cycle);
+                    break;
+                }
+                path.push(current);
+                current = employee.boss_id();
+            }
+            done.extend(path);
+        }
+        cycles
+    }
+
+    /// Builds the org chart. Orphans are shown at the top next to the real roots so nobody
+    /// goes missing; employees stuck in a cycle only appear in `cycles`.
+    pub fn build_chart(employees: &HashMap<u16, Employee>) -> OrgChart {
+        let orphans = orphans(employees);
+        let mut roots: Vec<u16> = employees
+            .values()
+            .filter(|emp| is_root(emp))
+            .map(|emp| emp.get_id())
+            .chain(orphans.iter().copied())
+            .collect();
+        roots.sort_unstable();
+        OrgChart {
+            roots: roots
+                .into_iter()
+                .filter_map(|id| build_node(employees, id, &mut HashSet::new()))
+                .collect(),
+            cycles: cycles(employees),
+            orphans,
+        }
+    }
+
+    fn build_node(
+        employees: &HashMap<u16, Employee>,
+        id: u16,
+        seen: &mut HashSet<u16>,
+    ) -> Option<OrgNode> {
+        if !seen.insert(id) {
+            return None;
+        }
+        let employee = employees.get(&id)?;
+        Some(OrgNode {
+            employee: EmployeeInfo::from(employee),
+            reports: direct_reports(employees, id)
+                .into_iter()
+                .filter_map(|report| build_node(employees, report, seen))
+                .collect(),
+        })
+    }
 }
 
 // Tests
@@ -105,7 +702,20 @@ pub mod security {
 
 #[cfg(test)]
 mod tests {
-    use crate::utils::security::{hash_str, verify_password};
+    use crate::utils::crypto;
+    use crate::utils::diff::json_diff;
+    use crate::utils::loading::replace_setting;
+    use crate::utils::migrate;
+    use crate::utils::org;
+    use crate::utils::totp;
+    use crate::utils::user::parse_value;
+    use crate::utils::security::{
+        hash_str, hash_with, needs_rehash_with, verify_password, HashParams,
+    };
+    use crate::types::enums::PermissionLevel;
+    use crate::types::structs::Employee;
+    use serde_json::json;
+    use std::collections::HashMap;
 
     #[test]
     fn test_hashing() {
@@ -130,4 +740,186 @@ mod tests {
             Err(_) => panic!("Hashing failed"),
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_json_diff() {
+        let before = json!({"id": 1, "name": "Sonic", "renter": null, "password": "a"});
+        let after = json!({"id": 1, "name": "Sonic", "renter": "John", "password": "b"});
+        assert_eq!(
+            json_diff(Some(&before), Some(&after), &["password"]),
+            json!({
+                "renter": {"before": null, "after": "John"},
+                "password": {"before": "[redacted]", "after": "[redacted]"}
+            })
+        );
+        assert_eq!(
+            json_diff(None, Some(&json!({"id": 1})), &[]),
+            json!({"id": {"before": null, "after": 1}})
+        );
+    }
+
+    #[test]
+    fn test_needs_rehash() {
+        let old_params = HashParams {
+            variant: argon2::Variant::Argon2i,
+            mem_cost: 1024,
+            time_cost: 1,
+            lanes: 1,
+        };
+        let new_params = HashParams {
+            variant: argon2::Variant::Argon2id,
+            ..old_params.clone()
+        };
+        let hash = hash_with("hello world", &old_params).unwrap();
+        assert!(!needs_rehash_with(&hash, &old_params));
+        assert!(needs_rehash_with(&hash, &new_params));
+        assert!(needs_rehash_with(
+            &hash,
+            &HashParams {
+                time_cost: 2,
+                ..old_params.clone()
+            }
+        ));
+        assert!(needs_rehash_with("not a hash", &old_params));
+        assert!(verify_password(&hash, "hello world").unwrap());
+    }
+
+    #[test]
+    fn test_totp_rfc_vectors() {
+        let secret = b"12345678901234567890";
+        assert_eq!(totp::code_at(secret, 59, 8), "94287082");
+        assert_eq!(totp::code_at(secret, 1111111109, 8), "07081804");
+        assert_eq!(totp::code_at(secret, 20000000000, 8), "65353130");
+    }
+
+    #[test]
+    fn test_totp_verify() {
+        let secret = totp::generate_secret();
+        let code = totp::code_at(&secret, 1_000_000, 6);
+        let step = 1_000_000 / 30;
+        assert_eq!(totp::verify(&secret, &code, 1_000_000, None), Some(step));
+        assert_eq!(totp::verify(&secret, &code, 1_000_030, None), Some(step));
+        assert_eq!(totp::verify(&secret, &code, 1_000_120, None), None);
+        assert_eq!(totp::verify(&secret, "12345", 1_000_000, None), None);
+        // A code that was accepted once cannot be used again
+        assert_eq!(totp::verify(&secret, &code, 1_000_000, Some(step)), None);
+        assert_eq!(totp::verify(&secret, &code, 1_000_000, Some(step - 1)), Some(step));
+        assert!(totp::provisioning_uri(&secret, "John Doe", "Library")
+            .starts_with("otpauth://totp/Library:John%20Doe?secret="));
+    }
+
+    #[test]
+    fn test_encrypt_round_trip() {
+        let key = crypto::derive_key("correct horse").unwrap();
+        let encrypted = crypto::encrypt(&key, b"secret");
+        assert_eq!(crypto::decrypt(&key, &encrypted).unwrap(), b"secret");
+
+        let other_key = crypto::derive_key("battery staple").unwrap();
+        assert!(crypto::decrypt(&other_key, &encrypted).is_err());
+
+        let setting = crypto::encrypt_setting(&key, "api-key");
+        assert!(crypto::is_encrypted(&setting));
+        assert_eq!(crypto::decrypt_setting(&key, &setting).unwrap(), "api-key");
+    }
+
+    #[test]
+    fn test_replace_setting() {
+        let contents = "[DBSettings]\napi_key = plain\n\n[Security]\napi_key = other\n";
+        assert_eq!(
+            replace_setting(contents, "Security", "api_key", "enc:abc").unwrap(),
+            "[DBSettings]\napi_key = plain\n\n[Security]\napi_key = enc:abc\n"
+        );
+        assert!(replace_setting(contents, "Security", "endpoint", "x").is_none());
+    }
+
+    #[test]
+    fn test_parse_value() {
+        assert_eq!(parse_value(&json!(false), "true").unwrap(), json!(true));
+        assert!(parse_value(&json!(0), "ten").is_err());
+        assert_eq!(parse_value(&json!(0), "10").unwrap(), json!(10));
+        assert_eq!(parse_value(&json!(""), "10").unwrap(), json!("10"));
+        assert_eq!(parse_value(&json!(null), "3").unwrap(), json!(3));
+        assert_eq!(parse_value(&json!(null), "Dev").unwrap(), json!("Dev"));
+        assert_eq!(parse_value(&json!([]), r#"["a"]"#).unwrap(), json!(["a"]));
+    }
+
+    #[test]
+    fn test_merge_names() {
+        let existing = [(1, "Information Technology")];
+        let values = [
+            (10, "information  technology"),
+            (11, "Sales"),
+            (12, " sales"),
+            (13, "SALES"),
+            (14, "sales"),
+            (15, ""),
+        ];
+        let merge = migrate::merge_names(&existing, &values, 5, migrate::normalize_whitespace);
+        assert_eq!(merge.created, vec![(5, "sales".to_string())]);
+        assert_eq!(merge.assignments, vec![(10, 1), (11, 5), (12, 5), (13, 5), (14, 5)]);
+    }
+
+    fn org_employees(bosses: &[(u16, u16)]) -> HashMap<u16, Employee> {
+        bosses
+            .iter()
+            .map(|&(id, boss_id)| {
+                let mut employee = Employee::default();
+                employee.set_id(id);
+                employee.set_boss_id(boss_id);
+                (id, employee)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_org_queries() {
+        // 1 runs everything, 4 and 5 report to each other and 7's boss does not exist
+        let employees = org_employees(&[(1, 1), (2, 1), (3, 2), (6, 2), (4, 5), (5, 4), (7, 9)]);
+        assert_eq!(org::direct_reports(&employees, 2), vec![3, 6]);
+        assert_eq!(org::all_reports(&employees, 1), vec![2, 3, 6]);
+        assert_eq!(org::chain_of_command(&employees, 3).unwrap(), vec![2, 1]);
+        assert!(org::chain_of_command(&employees, 1).unwrap().is_empty());
+        assert!(org::chain_of_command(&employees, 4).is_err());
+        assert!(org::chain_of_command(&employees, 7).is_err());
+        assert!(org::is_above(&employees, 1, 3));
+        assert!(!org::is_above(&employees, 3, 1));
+        assert!(!org::is_above(&employees, 2, 2));
+        assert_eq!(org::orphans(&employees), vec![7]);
+        assert_eq!(org::cycles(&employees), vec![vec![4, 5]]);
+
+        let chart = org::build_chart(&employees);
+        assert_eq!(chart.roots.len(), 2);
+        assert_eq!(chart.roots[0].reports.len(), 1);
+        assert_eq!(chart.roots[0].reports[0].reports.len(), 2);
+
+        // Older rows without a boss are roots, not orphans
+        let employees = org_employees(&[(1, 0), (2, 1)]);
+        assert!(org::orphans(&employees).is_empty());
+        assert_eq!(org::build_chart(&employees).roots.len(), 1);
+        assert_eq!(org::chain_of_command(&employees, 2).unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn test_org_authority() {
+        // 1 is a Manager with an Admin (2) and a User (3) reporting to them
+        let mut employees = org_employees(&[(1, 1), (2, 1), (3, 1), (4, 4)]);
+        for (id, level) in [
+            (1, PermissionLevel::Manager),
+            (2, PermissionLevel::Admin),
+            (3, PermissionLevel::User),
+            (4, PermissionLevel::Admin),
+        ] {
+            employees.get_mut(&id).unwrap().set_perm_level(level);
+        }
+        let manager = employees[&1].clone();
+        assert!(org::has_authority(&employees, &manager, 2));
+        assert!(org::can_change(&employees, &manager, 3));
+        assert!(!org::can_change(&employees, &manager, 2));
+        assert!(!org::has_authority(&employees, &manager, 4));
+
+        let admin = employees[&4].clone();
+        assert!(org::can_change(&employees, &admin, 1));
+        assert!(!org::can_change(&employees, &admin, 2));
+        assert!(!org::can_change(&employees, &admin, 4));
+    }
+}
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Media } from "./Media";
+
+export type AppEvent = { "kind": "media_changed", media: Media, } | { "kind": "media_removed", id: number, } | { "kind": "loan_created", media_id: number, renter: string, due: number, } | { "kind": "employee_updated", id: number, } | { "kind": "employee_removed", id: number, } | { "kind": "reloaded" };
\ No newline at end of file
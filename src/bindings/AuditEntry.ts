@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AuditAction } from "./AuditAction";
+
+export interface AuditEntry { id?: number, employee_id: number | null, timestamp: number, action: AuditAction, table_name: string, record_id: number, changes: unknown, }
\ No newline at end of file
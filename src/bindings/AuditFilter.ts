@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface AuditFilter { employee_id: number | null, table_name: string | null, from: number | null, to: number | null, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { PermissionLevel } from "./PermissionLevel";
+
+export interface EmployeeInfo { id: number, name: string, department: string, boss_id: number, project: string, subject: string, alloc_budget: number, perm_level: PermissionLevel, totp_enabled: boolean, branch_id: number, department_id: number | null, project_id: number | null, }
\ No newline at end of file
@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Choice } from "./Choice";
+import type { Table } from "./Table";
+
+export type FieldKind = { "type": "text" } | { "type": "password" } | { "type": "integer" } | { "type": "boolean" } | { "type": "timestamp" } | { "type": "choice", choices: Array<Choice>, } | { "type": "reference", table: Table, };
\ No newline at end of file
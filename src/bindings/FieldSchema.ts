@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { FieldKind } from "./FieldKind";
+
+export type FieldSchema = { name: string, optional: boolean, required: boolean, read_only: boolean, min: number | null, max: number | null, } & FieldKind;
\ No newline at end of file
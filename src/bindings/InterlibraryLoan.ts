@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { InterlibraryLoanStatus } from "./InterlibraryLoanStatus";
+import type { MediaType } from "./MediaType";
+
+export interface InterlibraryLoan { id: number, partner_id: number, title: string, media_type: MediaType, branch_id: number, requested_by: number, patron: string | null, status: InterlibraryLoanStatus, requested_at: number, updated_at: number, partner_due: number | null, patron_due: number | null, }
\ No newline at end of file
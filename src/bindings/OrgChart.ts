@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { OrgNode } from "./OrgNode";
+
+export interface OrgChart { roots: Array<OrgNode>, cycles: Array<Array<number>>, orphans: Array<number>, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { EmployeeInfo } from "./EmployeeInfo";
+
+export interface OrgNode { employee: EmployeeInfo, reports: Array<OrgNode>, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface PartnerLibrary { id: number, name: string, contact_email: string, address: string, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface Project { id: number, name: string, department_id: number | null, start: number, end: number | null, budget: number, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface ProjectSpend { project_id: number, name: string, start: number, end: number | null, budget: number, spent: number, remaining: number, }
\ No newline at end of file
@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { MediaType } from "./MediaType";
+import type { PurchaseStatus } from "./PurchaseStatus";
+
+export interface PurchaseRequest { id: number, requested_by: number, approver_id: number, department: string, project: string, project_id: number | null, title: string, media_type: MediaType, vendor: string, vendor_id: number | null, price: number, status: PurchaseStatus, requested_at: number, decided_at: number | null, media_id: number | null, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface RegistryMigration { departments_created: number, projects_created: number, employees_updated: number, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { MediaType } from "./MediaType";
+
+export interface SearchResult { id: number, media_type: MediaType, name: string, borrowable: boolean, vendor: string, renter: string | null, renter_id: number | null, due: number | null, vendor_id: number | null, branch_id: number, in_transit: boolean, interlibrary_loan: number | null, }
\ No newline at end of file
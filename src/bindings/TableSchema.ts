@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { FieldSchema } from "./FieldSchema";
+import type { Table } from "./Table";
+
+export interface TableSchema { table: Table, fields: Array<FieldSchema>, }
\ No newline at end of file
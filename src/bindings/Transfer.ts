@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { TransferStatus } from "./TransferStatus";
+
+export interface Transfer { id: number, media_id: number, source_branch: number, destination_branch: number, status: TransferStatus, requested_by: number, requested_at: number, updated_at: number, }
\ No newline at end of file
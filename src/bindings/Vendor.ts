@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface Vendor { id: number, name: string, contact_name: string, email: string, phone: string, account_number: string, notes: string, }
\ No newline at end of file
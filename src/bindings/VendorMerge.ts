@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Vendor } from "./Vendor";
+
+export interface VendorMerge { created: Array<Vendor>, assignments: Array<[number, number]>, }
\ No newline at end of file
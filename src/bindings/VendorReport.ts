@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface VendorReport { vendor_id: number, name: string, items: number, purchases: number, spent: number, }
\ No newline at end of file
@@ -26,13 +26,18 @@ import ArrowDownwardIcon from '@mui/icons-material/ArrowDownward';
 import Checkbox from '@mui/material/Checkbox';
 
 import { invoke } from "@tauri-apps/api/tauri";
+import { listen } from "@tauri-apps/api/event";
 import { useEffect, useState } from 'react';
 
+/** @typedef {import('../bindings/Media').Media} Media */
+/** @typedef {import('../bindings/SearchResult').SearchResult} SearchResult */
+/** @typedef {import('../bindings/AppEvent').AppEvent} AppEvent */
+
 export default function Content() {
   const [tabValue, setTabValue] = React.useState(0);
   const [search, setSearch] = React.useState("");
-  const [mediaData, setMediaData] = React.useState([]);
-  const [allMediaData, setAllMediaData] = React.useState([]);
+  const [mediaData, setMediaData] = React.useState(/** @type {SearchResult[]} */ ([]));
+  const [allMediaData, setAllMediaData] = React.useState(/** @type {Media[]} */ ([]));
   const [sortConfig, setSortConfig] = useState({ key: '', direction: 'ascending' });
   const [checkedItems, setCheckedItems] = useState({});
   const [checkoutData, setCheckoutData] = useState([]);
@@ -60,8 +65,7 @@ export default function Content() {
 
   async function searchMedia() {
     await invoke('search_media', { search: search })
-    .then((json) => {
-      const data = JSON.parse(json);
+    .then((/** @type {SearchResult[]} */ data) => {
       setMediaData(data);
     })
     .catch((error) => console.error('Error fetching media data:', error));
@@ -69,8 +73,7 @@ export default function Content() {
 
   async function getAllMedia() {
     await invoke('get_media')
-    .then((json) => {
-        const data = JSON.parse(json);
+    .then((/** @type {Media[]} */ data) => {
         setAllMediaData(data);
         const newCheckedItems = {};
         data.forEach(item => {
@@ -87,6 +90,32 @@ export default function Content() {
     }
   }, [tabValue]);
 
+  // Keep the lists in step with changes made from other windows
+  useEffect(() => {
+    const unlisten = listen('app-event', (/** @type {{payload: AppEvent}} */ { payload }) => {
+      switch (payload.kind) {
+        case 'media_changed': {
+          const media = payload.media;
+          setAllMediaData(prev => prev.some(item => item.id === media.id)
+            ? prev.map(item => item.id === media.id ? media : item)
+            : [...prev, media]);
+          setMediaData(prev => prev.map(item => item.id === media.id ? { ...item, ...media } : item));
+          break;
+        }
+        case 'media_removed':
+          setAllMediaData(prev => prev.filter(item => item.id !== payload.id));
+          setMediaData(prev => prev.filter(item => item.id !== payload.id));
+          break;
+        case 'reloaded':
+          getAllMedia();
+          break;
+        default:
+          break;
+      }
+    });
+    return () => { unlisten.then(stop => stop()); };
+  }, []);
+
   const renderMediaItem = (media) => {
     return (
       <Paper sx={{ my: 2, mx: 2, p: 2 }} key={media.id}>
@@ -139,13 +168,9 @@ export default function Content() {
   };
 
   async function handleCheckout() {
-    const jsonData = JSON.stringify(checkoutData);
-    await invoke('media_checkout', { cart: jsonData })
-    .then((json) => {
-      const data = JSON.parse(json);
-      setMediaData(data);
-    })
-    .catch((error) => console.error('Error fetching media data:', error));
+    await invoke('media_checkout', { cart: checkoutData.map(item => item.id) })
+    .then(() => getAllMedia())
+    .catch((error) => console.error('Error checking out media:', error));
   }
 
   const renderCheckoutContent = () => {